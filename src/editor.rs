@@ -2,8 +2,11 @@ use nih_plug::editor::Editor;
 use nih_plug_vizia::vizia::prelude::*;
 use nih_plug_vizia::widgets::*;
 use nih_plug_vizia::{assets, create_vizia_editor, ViziaState, ViziaTheming};
+use rand::Rng;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+use crate::engine::MAX_WORD_BITS;
 use crate::EntropeRustParams;
 
 #[derive(Lens)]
@@ -15,7 +18,7 @@ impl Model for Data {}
 
 // Makes sense to also define this here, makes it a bit easier to keep track of
 pub(crate) fn default_state() -> Arc<ViziaState> {
-    ViziaState::new(|| (400, 300))
+    ViziaState::new(|| (1000, 640))
 }
 
 pub(crate) fn create(
@@ -41,17 +44,123 @@ pub(crate) fn create(
                 .child_top(Stretch(1.0))
                 .child_bottom(Pixels(0.0));
 
-            Label::new(cx, "Crush");
-            ParamSlider::new(cx, Data::params, |params| &params.bit_depth);
-            Label::new(cx, "Redux");
-            ParamSlider::new(cx, Data::params, |params| &params.sample_rate);
-            Label::new(cx, "Entropy");
-            ParamSlider::new(cx, Data::params, |params| &params.entropy);
-            // Label::new(cx, "Clip");
-            // ParamSlider::new(cx, Data::params, |params| &params.clip);
+            HStack::new(cx, |cx| {
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "Crush");
+                    ParamSlider::new(cx, Data::params, |params| &params.bit_depth);
+                    ParamSlider::new(cx, Data::params, |params| &params.crush_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.mu);
+                    ParamSlider::new(cx, Data::params, |params| &params.a);
+                    ParamSlider::new(cx, Data::params, |params| &params.mantissa_bits);
+                    ParamSlider::new(cx, Data::params, |params| &params.exponent_bits);
+                    ParamSlider::new(cx, Data::params, |params| &params.quantizer_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.dither_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.dither_amount);
+                    ParamSlider::new(cx, Data::params, |params| &params.noise_shaping);
+                    ParamSlider::new(cx, Data::params, |params| &params.oversampling);
+                });
+
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "Redux");
+                    ParamSlider::new(cx, Data::params, |params| &params.redux_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.sample_rate);
+                    ParamSlider::new(cx, Data::params, |params| &params.redux_hz);
+                    ParamSlider::new(cx, Data::params, |params| &params.redux_phase);
+                    ParamSlider::new(cx, Data::params, |params| &params.redux_jitter);
+                    ParamSlider::new(cx, Data::params, |params| &params.redux_jitter_correlation);
+                    ParamSlider::new(cx, Data::params, |params| &params.anti_aliasing);
+                    ParamSlider::new(cx, Data::params, |params| &params.reconstruction);
+                    Label::new(cx, "Stutter");
+                    ParamSlider::new(cx, Data::params, |params| &params.stutter_length_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.stutter_length);
+                    ParamSlider::new(cx, Data::params, |params| &params.stutter_sync);
+                });
+
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "Entropy");
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_floor);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_source);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_rate_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_rate);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_sync);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_slew);
+                    ParamButton::new(cx, Data::params, |params| &params.deterministic);
+                    Button::new(
+                        cx,
+                        {
+                            let params = params.clone();
+                            move |_| {
+                                params
+                                    .seed
+                                    .store(rand::thread_rng().gen(), Ordering::Relaxed)
+                            }
+                        },
+                        |cx| Label::new(cx, "New Seed"),
+                    );
+                    ParamSlider::new(cx, Data::params, |params| &params.bit_error_rate);
+                    ParamSlider::new(cx, Data::params, |params| &params.bit_error_weighting);
+                });
+
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "Targets");
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_to_crush);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_to_redux);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_to_gain);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_to_mix);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_to_dropout);
+                    ParamSlider::new(cx, Data::params, |params| &params.entropy_to_stutter);
+                });
+
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "Dropout");
+                    ParamSlider::new(cx, Data::params, |params| &params.packet_loss);
+                    ParamSlider::new(cx, Data::params, |params| &params.packet_length);
+                    ParamSlider::new(cx, Data::params, |params| &params.concealment);
+                    Label::new(cx, "Output");
+                    ParamSlider::new(cx, Data::params, |params| &params.gain);
+                    ParamSlider::new(cx, Data::params, |params| &params.mix);
+                    ParamSlider::new(cx, Data::params, |params| &params.mix_law);
+                    Label::new(cx, "Clip");
+                    ParamSlider::new(cx, Data::params, |params| &params.clip_mode);
+                    ParamSlider::new(cx, Data::params, |params| &params.clip);
+                    ParamSlider::new(cx, Data::params, |params| &params.clip_placement);
+                });
+            })
+            .col_between(Pixels(15.0))
+            .height(Auto);
+
+            Label::new(cx, "Bits");
+            bit_grid(cx);
+            HStack::new(cx, |cx| {
+                ParamSlider::new(cx, Data::params, |params| &params.bit_shift_mode);
+                ParamSlider::new(cx, Data::params, |params| &params.bit_shift);
+            })
+            .col_between(Pixels(15.0))
+            .height(Auto);
         })
         .row_between(Pixels(0.0))
         .child_left(Stretch(1.0))
         .child_right(Stretch(1.0));
     })
 }
+
+/// The AND mask toggles above the XOR toggles for every bit of the bit mangler, MSB first.
+fn bit_grid(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        for bit in 0..MAX_WORD_BITS as usize {
+            ParamButton::new(cx, Data::params, move |params| &params.bits[bit].mask)
+                .with_label((bit + 1).to_string())
+                .width(Pixels(26.0));
+        }
+    })
+    .height(Auto);
+    HStack::new(cx, |cx| {
+        for bit in 0..MAX_WORD_BITS as usize {
+            ParamButton::new(cx, Data::params, move |params| &params.bits[bit].xor)
+                .with_label(String::from("^"))
+                .width(Pixels(26.0));
+        }
+    })
+    .height(Auto);
+}
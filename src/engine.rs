@@ -0,0 +1,1251 @@
+use nih_plug::prelude::*;
+use rand::prelude::*;
+
+mod anti_aliasing;
+mod biquad;
+mod bit_errors;
+mod bit_mangler;
+mod clipper;
+mod compander;
+mod delay;
+mod dither;
+mod entropy;
+mod entropy_sources;
+mod jitter;
+mod noise_shaper;
+mod oversampling;
+mod packet_loss;
+mod quantizer;
+mod reconstruction;
+mod stutter;
+#[cfg(test)]
+mod test_util;
+
+pub use anti_aliasing::AntiAliasing;
+pub use bit_errors::BitErrors;
+pub use bit_mangler::{BitMangler, BitShiftMode, MAX_WORD_BITS};
+pub use clipper::{clip, ClipMode, ClipPlacement};
+pub use compander::{a_law_compress, a_law_expand, mu_law_compress, mu_law_expand};
+pub use dither::DitherMode;
+pub use entropy::{
+    perturb_bit_depth, EntropyDepths, EntropyRateMode, NoteDivision, RandomClock,
+    MAX_ATTENUATION_DB, MAX_REDUX_OCTAVES, MIN_BIT_DEPTH,
+};
+pub use entropy_sources::{
+    Chaos, EntropySource, EntropySourceKind, EntropySources, Gaussian, Pink, RandomWalk, Smooth,
+    Uniform,
+};
+pub use noise_shaper::NoiseShaping;
+pub use oversampling::{Oversampling, MAX_OVERSAMPLING};
+pub use packet_loss::{Concealment, MAX_PACKET_LENGTH_MS};
+pub use quantizer::{quantize, reduce_float, CrushMode, QuantizerMode};
+pub use reconstruction::Reconstruction;
+pub use stutter::{SliceLengthMode, MAX_SLICE_LENGTH_MS};
+
+use anti_aliasing::AntiAliasingFilter;
+use delay::DelayLine;
+use dither::Dither;
+use jitter::Jitter;
+use noise_shaper::NoiseShaper;
+use oversampling::Oversampler;
+use packet_loss::PacketLoss;
+use stutter::Stutter;
+
+/// The longest hold length for [`ReduxMode::Divide`], in samples.
+pub const MAX_REDUX: i32 = 100;
+/// The lowest rate for [`ReduxMode::Frequency`], in Hz.
+pub const MIN_REDUX_HZ: f32 = 200.0;
+
+/// How the Redux amount is specified.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduxMode {
+    /// Hold every sample for an integer number of samples at the host's sample rate.
+    #[id = "divide"]
+    #[name = "Divide"]
+    Divide,
+    /// Resample to a fixed rate in Hz, independent of the host's sample rate.
+    #[id = "frequency"]
+    #[name = "Frequency"]
+    Frequency,
+}
+
+/// How the sample-and-hold points of the different channels relate to each other.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduxPhase {
+    /// Every channel grabs a new sample at the same time.
+    #[id = "linked"]
+    #[name = "Linked"]
+    Linked,
+    /// The hold points are spread out evenly over the channels, which widens the stereo image.
+    #[id = "independent"]
+    #[name = "Independent"]
+    Independent,
+}
+
+/// How the dry and processed signals are balanced by the Mix control. The laws assume the two
+/// signals are time aligned, which isn't always the case, see [`CrusherEngine::set_mix()`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixLaw {
+    /// The gains sum to one, which keeps correlated signals at the same level but dips in the
+    /// middle when the processed signal no longer resembles the dry signal.
+    #[id = "linear"]
+    #[name = "Linear"]
+    Linear,
+    /// The squared gains sum to one, which keeps uncorrelated signals at the same level.
+    #[id = "equal_power"]
+    #[name = "Equal Power"]
+    EqualPower,
+}
+
+impl MixLaw {
+    /// The gains for the processed and dry signals for a mix amount between `0.0` and `1.0`.
+    pub fn gains(self, mix: f32) -> (f32, f32) {
+        match self {
+            MixLaw::Linear => (mix, 1.0 - mix),
+            MixLaw::EqualPower => {
+                let (sin, cos) = (mix * std::f32::consts::FRAC_PI_2).sin_cos();
+                (sin, cos)
+            }
+        }
+    }
+}
+
+/// The crusher DSP without any of the plugin plumbing, so it can be driven from anything that can
+/// hand it a block of de-interleaved channels.
+pub struct CrusherEngine {
+    sample_rate: f32,
+    /// The target bit depth. This can be fractional, in which case the number of quantization
+    /// levels falls in between two powers of two.
+    bit_depth: f32,
+    bit_depth_smoother: Smoother<f32>,
+    crush_mode: CrushMode,
+    /// The mu parameter for [`CrushMode::MuLaw`].
+    mu: f32,
+    /// The A parameter for [`CrushMode::ALaw`].
+    a: f32,
+    /// The format used for [`CrushMode::Float`].
+    mantissa_bits: u32,
+    exponent_bits: u32,
+    quantizer_mode: QuantizerMode,
+    dither_mode: DitherMode,
+    /// The dither level relative to the mode's nominal level.
+    dither_amount: f32,
+    noise_shaping: NoiseShaping,
+    oversampling: Oversampling,
+    bit_mangler: BitMangler,
+    /// The bit error rate gets scaled by the entropy amount.
+    bit_errors: BitErrors,
+    redux_mode: ReduxMode,
+    redux: i32,
+    redux_hz: f32,
+    redux_phase: ReduxPhase,
+    /// The maximum random deviation of a hold interval, in octaves.
+    redux_jitter: f32,
+    /// How strongly consecutive jitter values are correlated, between `0.0` and `1.0`.
+    redux_jitter_correlation: f32,
+    anti_aliasing: AntiAliasing,
+    reconstruction: Reconstruction,
+    /// The Entropy amount, between `0.0` and `1.0`.
+    entropy: f32,
+    /// The lowest bit depth Entropy can push the Crush stage to.
+    entropy_floor: f32,
+    /// How often Entropy picks a new random value, in Hz.
+    entropy_rate: f32,
+    /// The fraction of an Entropy interval it takes to glide to a new random value.
+    entropy_slew: f32,
+    entropy_source: EntropySourceKind,
+    entropy_depths: EntropyDepths,
+    /// The stutter slice length in milliseconds.
+    stutter_length_ms: f32,
+    /// The chance of a packet getting lost, between `0.0` and `1.0`.
+    packet_loss: f32,
+    /// The packet length in milliseconds.
+    packet_length_ms: f32,
+    concealment: Concealment,
+    /// The output gain, as a linear gain.
+    gain: f32,
+    gain_smoother: Smoother<f32>,
+    /// The amount of processed signal in the output, between `0.0` and `1.0`.
+    mix: f32,
+    mix_smoother: Smoother<f32>,
+    mix_law: MixLaw,
+    clip_mode: ClipMode,
+    /// The clipping threshold, as a linear gain.
+    clip_threshold: f32,
+    clip_threshold_smoother: Smoother<f32>,
+    clip_placement: ClipPlacement,
+
+    gen: StdRng,
+    entropy_clock: RandomClock,
+    entropy_sources: EntropySources,
+    dither: Dither,
+    noise_shaper: NoiseShaper,
+    oversampler: Oversampler,
+    anti_aliasing_filter: AntiAliasingFilter,
+    /// The same steep low-pass filter as `anti_aliasing_filter`, used for
+    /// [`Reconstruction::Dac`].
+    reconstruction_filter: AntiAliasingFilter,
+    packet_loss_state: PacketLoss,
+    /// Delays the dry signal by [`dry_delay()`][Self::dry_delay()] so it lines up with the
+    /// processed signal.
+    dry_delay: DelayLine,
+    stutter_state: Stutter,
+    /// The last four held samples for every channel, with the currently held sample first.
+    reduced: Vec<[f32; 4]>,
+    /// The position within the current hold interval. This carries over between blocks so the
+    /// decimation grid does not depend on the host's buffer size.
+    hold_phase: i32,
+    /// The same as `hold_phase`, but as a fraction of a hold interval for [`ReduxMode::Frequency`].
+    hold_phase_hz: f64,
+    jitter: Jitter,
+    /// The length multiplier for the current hold interval.
+    jitter_factor: f32,
+}
+
+impl CrusherEngine {
+    /// Smooths out bit depth automation so sweeping Crush doesn't zipper.
+    fn bit_depth_smoother(initial: f32) -> Smoother<f32> {
+        let smoother = Smoother::new(SmoothingStyle::Linear(20.0));
+        smoother.reset(initial);
+
+        smoother
+    }
+
+    /// Also used for the clipping threshold.
+    fn gain_smoother(initial: f32) -> Smoother<f32> {
+        let smoother = Smoother::new(SmoothingStyle::Logarithmic(50.0));
+        smoother.reset(initial);
+
+        smoother
+    }
+
+    fn mix_smoother(initial: f32) -> Smoother<f32> {
+        let smoother = Smoother::new(SmoothingStyle::Linear(50.0));
+        smoother.reset(initial);
+
+        smoother
+    }
+
+    /// The number of samples needed to store the longest possible packet.
+    fn max_packet_length(sample_rate: f32) -> usize {
+        (MAX_PACKET_LENGTH_MS / 1000.0 * sample_rate).ceil() as usize
+    }
+
+    /// The number of samples needed to store the longest possible stutter slice.
+    fn max_slice_length(sample_rate: f32) -> usize {
+        (MAX_SLICE_LENGTH_MS / 1000.0 * sample_rate).ceil() as usize
+    }
+
+    /// The longest possible [`dry_delay()`][Self::dry_delay()], which is two of the longest hold
+    /// intervals plus the oversampling latency.
+    fn max_dry_delay(sample_rate: f32) -> usize {
+        let max_hold_length =
+            (MAX_REDUX as usize).max((sample_rate / MIN_REDUX_HZ).ceil() as usize);
+
+        (2 * max_hold_length) + Oversampling::X8.latency_samples() as usize
+    }
+
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            sample_rate: 44100.0,
+            bit_depth: 24.0,
+            bit_depth_smoother: Self::bit_depth_smoother(24.0),
+            crush_mode: CrushMode::Linear,
+            mu: 255.0,
+            a: 87.6,
+            mantissa_bits: 10,
+            exponent_bits: 5,
+            quantizer_mode: QuantizerMode::Truncate,
+            dither_mode: DitherMode::Off,
+            dither_amount: 1.0,
+            noise_shaping: NoiseShaping::Off,
+            oversampling: Oversampling::Off,
+            bit_mangler: BitMangler::default(),
+            bit_errors: BitErrors::default(),
+            redux_mode: ReduxMode::Divide,
+            redux: 1,
+            redux_hz: 44100.0,
+            redux_phase: ReduxPhase::Linked,
+            redux_jitter: 0.0,
+            redux_jitter_correlation: 0.0,
+            anti_aliasing: AntiAliasing::Off,
+            reconstruction: Reconstruction::ZeroOrderHold,
+            entropy: 0.0,
+            entropy_floor: MIN_BIT_DEPTH,
+            entropy_rate: 10.0,
+            entropy_slew: 0.0,
+            entropy_source: EntropySourceKind::Uniform,
+            entropy_depths: EntropyDepths::default(),
+            stutter_length_ms: 125.0,
+            packet_loss: 0.0,
+            packet_length_ms: 20.0,
+            concealment: Concealment::Silence,
+            gain: 1.0,
+            gain_smoother: Self::gain_smoother(1.0),
+            mix: 1.0,
+            mix_smoother: Self::mix_smoother(1.0),
+            mix_law: MixLaw::Linear,
+            clip_mode: ClipMode::Off,
+            clip_threshold: 1.0,
+            clip_threshold_smoother: Self::gain_smoother(1.0),
+            clip_placement: ClipPlacement::PostCrush,
+
+            gen: StdRng::from_entropy(),
+            entropy_clock: RandomClock::default(),
+            entropy_sources: EntropySources::default(),
+            dither: Dither::new(num_channels),
+            noise_shaper: NoiseShaper::new(num_channels),
+            oversampler: Oversampler::new(num_channels),
+            anti_aliasing_filter: AntiAliasingFilter::new(num_channels),
+            reconstruction_filter: AntiAliasingFilter::new(num_channels),
+            dry_delay: DelayLine::new(num_channels, Self::max_dry_delay(44100.0)),
+            packet_loss_state: PacketLoss::new(num_channels, Self::max_packet_length(44100.0)),
+            stutter_state: Stutter::new(num_channels, Self::max_slice_length(44100.0)),
+            reduced: vec![[0.0; 4]; num_channels],
+            hold_phase: 0,
+            hold_phase_hz: 0.0,
+            jitter: Jitter::default(),
+            jitter_factor: 1.0,
+        }
+    }
+
+    /// The same as [`new()`][Self::new()], but with a fixed seed for the random number generator so
+    /// the output is reproducible.
+    pub fn with_seed(num_channels: usize, seed: u64) -> Self {
+        Self {
+            gen: StdRng::seed_from_u64(seed),
+            ..Self::new(num_channels)
+        }
+    }
+
+    /// Reseed the random number generator. This also restarts the Entropy clock and sources so
+    /// the next random value is drawn from the new seed right away. Does not allocate.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.gen = StdRng::seed_from_u64(seed);
+        self.entropy_clock.reset();
+        self.entropy_sources.reset();
+    }
+
+    /// Resize the per-channel state. This allocates, so it should only be called when setting up
+    /// the engine, not from the audio thread.
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        self.dither.set_num_channels(num_channels);
+        self.noise_shaper.set_num_channels(num_channels);
+        self.oversampler.set_num_channels(num_channels);
+        self.dry_delay.set_num_channels(num_channels);
+        self.anti_aliasing_filter.set_num_channels(num_channels);
+        self.reconstruction_filter.set_num_channels(num_channels);
+        self.reduced.resize(num_channels, [0.0; 4]);
+        self.packet_loss_state
+            .resize(num_channels, Self::max_packet_length(self.sample_rate));
+        self.stutter_state
+            .resize(num_channels, Self::max_slice_length(self.sample_rate));
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.reduced.len()
+    }
+
+    /// Set the sample rate. This resizes the packet loss, stutter and dry delay buffers, so just
+    /// like [`set_num_channels()`][Self::set_num_channels()] it allocates.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.dry_delay = DelayLine::new(self.num_channels(), Self::max_dry_delay(sample_rate));
+        self.packet_loss_state
+            .resize(self.num_channels(), Self::max_packet_length(sample_rate));
+        self.stutter_state
+            .resize(self.num_channels(), Self::max_slice_length(sample_rate));
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Clear all of the running state. Does not allocate, so this is safe to call from the audio
+    /// thread.
+    pub fn reset(&mut self) {
+        self.entropy_clock.reset();
+        self.entropy_sources.reset();
+        self.dither.reset();
+        self.noise_shaper.reset();
+        self.oversampler.reset();
+        self.dry_delay.reset();
+        self.anti_aliasing_filter.reset();
+        self.reconstruction_filter.reset();
+        self.packet_loss_state.reset();
+        self.stutter_state.reset();
+        self.reduced.fill([0.0; 4]);
+        self.hold_phase = 0;
+        self.hold_phase_hz = 0.0;
+        self.jitter.reset();
+        self.jitter_factor = 1.0;
+        self.bit_depth_smoother.reset(self.bit_depth);
+        self.gain_smoother.reset(self.gain);
+        self.mix_smoother.reset(self.mix);
+        self.clip_threshold_smoother.reset(self.clip_threshold);
+    }
+
+    /// Set the target bit depth. This is meant to be called for every block, so the smoother only
+    /// gets retriggered when the value actually changes. Otherwise the ramp would restart every
+    /// block and its length would depend on the host's buffer size.
+    pub fn set_bit_depth(&mut self, bit_depth: f32) {
+        if bit_depth != self.bit_depth {
+            self.bit_depth = bit_depth;
+            self.bit_depth_smoother
+                .set_target(self.sample_rate, bit_depth);
+        }
+    }
+
+    pub fn set_crush_mode(&mut self, crush_mode: CrushMode) {
+        self.crush_mode = crush_mode;
+    }
+
+    /// Used with [`CrushMode::MuLaw`].
+    pub fn set_mu(&mut self, mu: f32) {
+        self.mu = mu;
+    }
+
+    /// Used with [`CrushMode::ALaw`].
+    pub fn set_a(&mut self, a: f32) {
+        self.a = a;
+    }
+
+    /// Used with [`CrushMode::Float`].
+    pub fn set_mantissa_bits(&mut self, mantissa_bits: u32) {
+        self.mantissa_bits = mantissa_bits;
+    }
+
+    /// Used with [`CrushMode::Float`]. Anything at or above 8 bits leaves the exponent alone.
+    pub fn set_exponent_bits(&mut self, exponent_bits: u32) {
+        self.exponent_bits = exponent_bits;
+    }
+
+    pub fn set_quantizer_mode(&mut self, quantizer_mode: QuantizerMode) {
+        self.quantizer_mode = quantizer_mode;
+    }
+
+    pub fn set_dither_mode(&mut self, dither_mode: DitherMode) {
+        self.dither_mode = dither_mode;
+    }
+
+    /// Scales the dither noise, `1.0` is the nominal level for the dither mode.
+    pub fn set_dither_amount(&mut self, dither_amount: f32) {
+        self.dither_amount = dither_amount;
+    }
+
+    pub fn set_noise_shaping(&mut self, noise_shaping: NoiseShaping) {
+        self.noise_shaping = noise_shaping;
+    }
+
+    /// Run the Crush stage at a multiple of the sample rate so the harmonics it generates don't
+    /// alias back into the audible range. This adds latency, see
+    /// [`latency_samples()`][Self::latency_samples()]. Noise shaping runs at the oversampled rate
+    /// too, which throws off [`NoiseShaping::FWeighted`]'s 44.1 kHz curve.
+    pub fn set_oversampling(&mut self, oversampling: Oversampling) {
+        // Stages that get switched back on would otherwise replay whatever was left in their
+        // delay lines, and the dry delay needs to start over at the new latency
+        if oversampling != self.oversampling {
+            self.oversampling = oversampling;
+            self.oversampler.reset();
+            self.dry_delay.reset();
+        }
+    }
+
+    /// The latency introduced by the engine in samples, which should be reported to the host.
+    pub fn latency_samples(&self) -> u32 {
+        self.oversampling.latency_samples()
+    }
+
+    /// How far the dry signal gets delayed to line up with the processed signal, in samples. On
+    /// top of the oversampling latency this includes the delay of the reconstruction mode at the
+    /// nominal Redux setting. Entropy and jitter modulate the hold length around that, and those
+    /// deviations aren't compensated.
+    fn dry_delay(&self) -> usize {
+        let hold_length = match self.redux_mode {
+            ReduxMode::Divide => self.redux as f32,
+            ReduxMode::Frequency => self.sample_rate / self.redux_hz,
+        };
+        // Without any reduction the reconstruction stage is bypassed
+        let reconstruction_delay = if hold_length > 1.0 {
+            (self.reconstruction.delay_intervals() as f32 * hold_length).round() as usize
+        } else {
+            0
+        };
+
+        self.latency_samples() as usize + reconstruction_delay
+    }
+
+    pub fn set_bit_mangler(&mut self, bit_mangler: BitMangler) {
+        self.bit_mangler = bit_mangler;
+    }
+
+    pub fn set_bit_errors(&mut self, bit_errors: BitErrors) {
+        self.bit_errors = bit_errors;
+    }
+
+    pub fn set_redux_mode(&mut self, redux_mode: ReduxMode) {
+        self.redux_mode = redux_mode;
+    }
+
+    /// Hold every sample for this many samples, `1` means no reduction. Used with
+    /// [`ReduxMode::Divide`].
+    pub fn set_redux(&mut self, redux: i32) {
+        self.redux = redux;
+    }
+
+    /// The rate in Hz to resample to. Anything at or above the host's sample rate means no
+    /// reduction. Used with [`ReduxMode::Frequency`].
+    pub fn set_redux_hz(&mut self, redux_hz: f32) {
+        self.redux_hz = redux_hz;
+    }
+
+    pub fn set_redux_phase(&mut self, redux_phase: ReduxPhase) {
+        self.redux_phase = redux_phase;
+    }
+
+    /// Randomly stretch and squash every hold interval by up to this many octaves, `0.0` holds
+    /// for exactly the nominal length.
+    pub fn set_redux_jitter(&mut self, redux_jitter: f32) {
+        self.redux_jitter = redux_jitter;
+    }
+
+    /// How strongly consecutive jitter values are correlated, between `0.0` for independent
+    /// values and `1.0` for a slow drift.
+    pub fn set_redux_jitter_correlation(&mut self, redux_jitter_correlation: f32) {
+        self.redux_jitter_correlation = redux_jitter_correlation;
+    }
+
+    /// The slope of the low-pass filter in front of the sample-and-hold. The cutoff follows the
+    /// effective Redux rate, including Entropy and jitter.
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+    }
+
+    /// How the held samples are turned back into a continuous signal.
+    pub fn set_reconstruction(&mut self, reconstruction: Reconstruction) {
+        self.reconstruction = reconstruction;
+    }
+
+    /// Set the Entropy amount, between `0.0` and `1.0`.
+    pub fn set_entropy(&mut self, entropy: f32) {
+        self.entropy = entropy;
+    }
+
+    /// The lowest bit depth Entropy can push the Crush stage to, see [`perturb_bit_depth()`].
+    pub fn set_entropy_floor(&mut self, entropy_floor: f32) {
+        self.entropy_floor = entropy_floor;
+    }
+
+    /// How often Entropy picks a new random value, in Hz. Rates at or above the sample rate pick a
+    /// new value for every sample.
+    pub fn set_entropy_rate(&mut self, entropy_rate: f32) {
+        self.entropy_rate = entropy_rate;
+    }
+
+    /// Line Entropy's intervals up with the host's beat grid. `position` is the transport position
+    /// at the start of the next block, measured in Entropy intervals. Meant to be called before
+    /// every block while a synced rate is used and the transport is playing.
+    pub fn sync_entropy_clock(&mut self, position: f64) {
+        self.entropy_clock.set_position(position);
+    }
+
+    /// The fraction of an Entropy interval it takes to glide to a new random value, between `0.0`
+    /// and `1.0`.
+    pub fn set_entropy_slew(&mut self, entropy_slew: f32) {
+        self.entropy_slew = entropy_slew;
+    }
+
+    pub fn set_entropy_source(&mut self, entropy_source: EntropySourceKind) {
+        self.entropy_source = entropy_source;
+    }
+
+    pub fn set_entropy_depths(&mut self, entropy_depths: EntropyDepths) {
+        self.entropy_depths = entropy_depths;
+    }
+
+    /// The stutter slice length in milliseconds, up to [`MAX_SLICE_LENGTH_MS`]. How often slices
+    /// get stuttered is set by Entropy, see [`EntropyDepths::stutter`].
+    pub fn set_stutter_length_ms(&mut self, stutter_length_ms: f32) {
+        self.stutter_length_ms = stutter_length_ms;
+    }
+
+    /// The chance of a packet getting lost, between `0.0` and `1.0`.
+    pub fn set_packet_loss(&mut self, packet_loss: f32) {
+        self.packet_loss = packet_loss;
+    }
+
+    /// The packet length in milliseconds, up to [`MAX_PACKET_LENGTH_MS`].
+    pub fn set_packet_length_ms(&mut self, packet_length_ms: f32) {
+        self.packet_length_ms = packet_length_ms;
+    }
+
+    pub fn set_concealment(&mut self, concealment: Concealment) {
+        self.concealment = concealment;
+    }
+
+    /// Set the output gain, as a linear gain. Like
+    /// [`set_bit_depth()`][Self::set_bit_depth()], this only retriggers the smoother on changes.
+    pub fn set_gain(&mut self, gain: f32) {
+        if gain != self.gain {
+            self.gain = gain;
+            self.gain_smoother.set_target(self.sample_rate, gain);
+        }
+    }
+
+    /// Set the amount of processed signal in the output, between `0.0` and `1.0`.
+    ///
+    /// The dry signal is delayed by the oversampling latency and by the one or two hold intervals
+    /// [`Reconstruction::Linear`] and [`Reconstruction::Cubic`] run behind at the nominal Redux
+    /// setting. Entropy and jitter modulation of the hold length, and the group delay of the
+    /// anti-aliasing and DAC filters, aren't compensated, so partial mixes with those will comb
+    /// filter.
+    pub fn set_mix(&mut self, mix: f32) {
+        if mix != self.mix {
+            self.mix = mix;
+            self.mix_smoother.set_target(self.sample_rate, mix);
+        }
+    }
+
+    pub fn set_mix_law(&mut self, mix_law: MixLaw) {
+        self.mix_law = mix_law;
+    }
+
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.clip_mode = clip_mode;
+    }
+
+    /// Set the clipping threshold, as a linear gain.
+    pub fn set_clip_threshold(&mut self, clip_threshold: f32) {
+        if clip_threshold != self.clip_threshold {
+            self.clip_threshold = clip_threshold;
+            self.clip_threshold_smoother
+                .set_target(self.sample_rate, clip_threshold);
+        }
+    }
+
+    pub fn set_clip_placement(&mut self, clip_placement: ClipPlacement) {
+        self.clip_placement = clip_placement;
+    }
+
+    /// Process a block of audio in place. Every channel slice needs to have the same length. Only
+    /// the first [`num_channels()`][Self::num_channels()] channels are processed.
+    pub fn process_block(&mut self, channels: &mut [&mut [f32]]) {
+        let num_samples = channels.first().map_or(0, |channel| channel.len());
+        let num_channels = channels.len().min(self.reduced.len());
+
+        let bit_errors = BitErrors {
+            rate: self.bit_errors.rate * self.entropy,
+            ..self.bit_errors
+        };
+
+        let entropy_increment = self.entropy_rate as f64 / self.sample_rate as f64;
+        let depths = self.entropy_depths;
+        let slice_length = (self.stutter_length_ms / 1000.0 * self.sample_rate).round() as usize;
+        let packet_length = (self.packet_length_ms / 1000.0 * self.sample_rate).round() as usize;
+        let dry_delay = self.dry_delay();
+
+        for i in 0..num_samples {
+            let source = self.entropy_sources.get_mut(self.entropy_source);
+            let gen = &mut self.gen;
+            let random = self
+                .entropy_clock
+                .next(entropy_increment, self.entropy_slew, || source.next(gen));
+            let bit_depth = perturb_bit_depth(
+                self.bit_depth_smoother.next(),
+                self.entropy * depths.bit_depth,
+                self.entropy_floor,
+                random,
+            );
+            let total_q_levels = bit_depth.exp2();
+            let step = 1.0 / total_q_levels;
+
+            let redux_octaves = self.entropy * depths.redux * random * MAX_REDUX_OCTAVES;
+            // Jitter only varies the length of a hold that's already there, it shouldn't start
+            // holding samples when there's no reduction
+            let reducing = match self.redux_mode {
+                ReduxMode::Divide => self.redux > 1,
+                ReduxMode::Frequency => self.redux_hz < self.sample_rate,
+            };
+            let jitter_factor = if reducing { self.jitter_factor } else { 1.0 };
+            let hold_factor = redux_octaves.exp2() * jitter_factor;
+            let redux = (self.redux as f32 * hold_factor).round() as i32;
+            // The phase increment for `ReduxMode::Frequency`
+            let hold_increment =
+                self.redux_hz as f64 / hold_factor as f64 / self.sample_rate as f64;
+            // The reduced sample rate relative to the host's sample rate
+            let reduced_rate = match self.redux_mode {
+                ReduxMode::Divide => 1.0 / redux.max(1) as f32,
+                ReduxMode::Frequency => hold_increment.min(1.0) as f32,
+            };
+            let anti_aliasing = self.anti_aliasing != AntiAliasing::Off && reduced_rate < 1.0;
+            if anti_aliasing {
+                self.anti_aliasing_filter
+                    .update(self.anti_aliasing, reduced_rate);
+            }
+            let dac_filter = self.reconstruction == Reconstruction::Dac && reduced_rate < 1.0;
+            if dac_filter {
+                self.reconstruction_filter
+                    .update(AntiAliasing::Slope48, reduced_rate);
+            }
+
+            let attenuation_db = self.entropy * depths.gain * random * MAX_ATTENUATION_DB;
+            let gain = self.gain_smoother.next() * util::db_to_gain(-attenuation_db);
+            let mix = self.mix_smoother.next() * (1.0 - (self.entropy * depths.mix * random));
+            let (wet_gain, dry_gain) = self.mix_law.gains(mix);
+            let clip_threshold = self.clip_threshold_smoother.next();
+            let (pre_clip, post_clip) = match self.clip_placement {
+                ClipPlacement::PreCrush => (self.clip_mode, ClipMode::Off),
+                ClipPlacement::PostCrush => (ClipMode::Off, self.clip_mode),
+            };
+            let stutter = self.entropy * depths.stutter * random;
+            self.stutter_state
+                .start_sample(slice_length, stutter, &mut self.gen);
+            let packet_loss =
+                (self.packet_loss + (self.entropy * depths.dropout * random)).min(1.0);
+            self.packet_loss_state
+                .start_sample(packet_length, packet_loss, &mut self.gen);
+
+            for (c, channel) in channels[..num_channels].iter_mut().enumerate() {
+                let sample = &mut channel[i];
+                let dry = *sample;
+
+                *sample = self.stutter_state.process(c, *sample);
+                *sample = clip(*sample, clip_threshold, pre_clip);
+                // This sits in front of the Crush stage, just like the input filter in front of a
+                // sampler's converter
+                if anti_aliasing {
+                    *sample = self.anti_aliasing_filter.process(c, *sample);
+                }
+                *sample = match self.oversampling {
+                    Oversampling::Off => self.crush(c, *sample, step),
+                    _ => self.crush_oversampled(c, *sample, step),
+                };
+                if !self.bit_mangler.is_identity() {
+                    *sample = self.bit_mangler.process(*sample, bit_depth.round() as u32);
+                }
+                if bit_errors.is_active() {
+                    *sample = bit_errors.process(*sample, bit_depth.round() as u32, &mut self.gen);
+                }
+                *sample = self.redux(c, num_channels, *sample, redux, hold_increment);
+                if dac_filter {
+                    *sample = self.reconstruction_filter.process(c, *sample);
+                }
+
+                *sample *= gain;
+                *sample = clip(*sample, clip_threshold, post_clip);
+                *sample =
+                    self.packet_loss_state
+                        .process(c, *sample, self.concealment, &mut self.gen);
+                let dry = self.dry_delay.process(c, dry, dry_delay);
+                *sample = (*sample * wet_gain) + (dry * dry_gain);
+            }
+
+            self.stutter_state.end_sample();
+            self.dry_delay.end_sample();
+            self.packet_loss_state.end_sample();
+            self.hold_phase = (self.hold_phase + 1) % redux.max(1);
+            let hold_phase_hz = self.hold_phase_hz + hold_increment;
+            self.hold_phase_hz = hold_phase_hz.fract();
+
+            // Every hold interval gets its own random length
+            let new_interval = match self.redux_mode {
+                ReduxMode::Divide => self.hold_phase == 0,
+                ReduxMode::Frequency => hold_phase_hz >= 1.0,
+            };
+            if new_interval {
+                self.jitter_factor = if self.redux_jitter > 0.0 {
+                    self.jitter.next(
+                        self.redux_jitter,
+                        self.redux_jitter_correlation,
+                        &mut self.gen,
+                    )
+                } else {
+                    1.0
+                };
+            }
+        }
+    }
+
+    /// The Crush stage for a single sample, run at [`Oversampling::factor()`] times the sample
+    /// rate.
+    fn crush_oversampled(&mut self, channel: usize, sample: f32, step: f32) -> f32 {
+        let mut oversampled = [0.0; MAX_OVERSAMPLING];
+        self.oversampler
+            .upsample(channel, self.oversampling, sample, &mut oversampled);
+        for sample in &mut oversampled[..self.oversampling.factor()] {
+            *sample = self.crush(channel, *sample, step);
+        }
+
+        self.oversampler
+            .downsample(channel, self.oversampling, &mut oversampled)
+    }
+
+    /// The Crush stage for a single sample. `step` is the quantization step size for the
+    /// fixed-point modes.
+    fn crush(&mut self, channel: usize, sample: f32, step: f32) -> f32 {
+        let compressed = match self.crush_mode {
+            CrushMode::Linear => sample,
+            CrushMode::MuLaw => mu_law_compress(sample, self.mu),
+            CrushMode::ALaw => a_law_compress(sample, self.a),
+            CrushMode::Float => {
+                return reduce_float(sample, self.mantissa_bits, self.exponent_bits);
+            }
+        };
+
+        // The F-weighted filter has a peak gain of over 20, so at low bit depths the feedback alone
+        // can be many times full scale. Clamping the quantizer's input keeps both the output and
+        // the error feedback loop bounded.
+        let shaped =
+            (compressed - self.noise_shaper.feedback(channel, self.noise_shaping)).clamp(-1.0, 1.0);
+        let mut dithered = shaped;
+        if self.dither_mode != DitherMode::Off {
+            let dither = self.dither.next(channel, self.dither_mode, &mut self.gen);
+            dithered += dither * step * self.dither_amount;
+        }
+        let quantized = quantize(dithered, step, self.quantizer_mode);
+        // The dither is part of the error, so it gets shaped along with the quantization noise
+        if self.noise_shaping != NoiseShaping::Off {
+            self.noise_shaper.push_error(channel, quantized - shaped);
+        }
+
+        match self.crush_mode {
+            CrushMode::MuLaw => mu_law_expand(quantized, self.mu),
+            CrushMode::ALaw => a_law_expand(quantized, self.a),
+            _ => quantized,
+        }
+    }
+
+    /// The Redux sample-and-hold stage for a single sample. `redux` is the hold length for
+    /// [`ReduxMode::Divide`] and `hold_increment` is the phase increment for
+    /// [`ReduxMode::Frequency`], both after Entropy modulation. The held samples are then
+    /// interpolated according to the reconstruction mode.
+    fn redux(
+        &mut self,
+        channel: usize,
+        num_channels: usize,
+        sample: f32,
+        redux: i32,
+        hold_increment: f64,
+    ) -> f32 {
+        // The position within the current hold interval, between `0.0` and `1.0`, and whether the
+        // previous sample should be held
+        let (t, hold) = match self.redux_mode {
+            ReduxMode::Divide if redux > 1 => {
+                let offset = match self.redux_phase {
+                    ReduxPhase::Linked => 0,
+                    ReduxPhase::Independent => (channel as i32 * redux) / num_channels as i32,
+                };
+
+                let position = (self.hold_phase + offset) % redux;
+                (position as f32 / redux as f32, position != 0)
+            }
+            ReduxMode::Frequency if hold_increment < 1.0 => {
+                let offset = match self.redux_phase {
+                    ReduxPhase::Linked => 0.0,
+                    ReduxPhase::Independent => channel as f64 / num_channels as f64,
+                };
+
+                // A new sample is taken on the first host sample after the phase wraps around
+                let position = (self.hold_phase_hz + offset).fract();
+                (position as f32, position >= hold_increment)
+            }
+            // Without any reduction there's nothing to hold or interpolate
+            _ => return sample,
+        };
+
+        let history = &mut self.reduced[channel];
+        if !hold {
+            history.copy_within(..3, 1);
+            history[0] = sample;
+        }
+
+        self.reconstruction.interpolate(history, t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::band_energy;
+
+    /// A rising sine sweep with a slow fade, with a different phase for every channel.
+    fn test_signal(num_channels: usize, num_samples: usize) -> Vec<Vec<f32>> {
+        (0..num_channels)
+            .map(|c| {
+                (0..num_samples)
+                    .map(|i| {
+                        let t = i as f32 / 44100.0;
+                        let phase = (t * 220.0) + (t * t * 400.0) + (c as f32 * 0.25);
+                        0.8 * (phase * std::f32::consts::TAU).sin() * (1.0 - (t * 0.1))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn process(engine: &mut CrusherEngine, signal: &mut [Vec<f32>]) {
+        let mut channels: Vec<&mut [f32]> = signal.iter_mut().map(Vec::as_mut_slice).collect();
+        engine.process_block(&mut channels);
+    }
+
+    #[test]
+    fn mono_passes_through_at_full_resolution() {
+        let input = test_signal(1, 4096);
+        let mut output = input.clone();
+        let mut engine = CrusherEngine::new(1);
+        process(&mut engine, &mut output);
+
+        for (x, y) in input[0].iter().zip(&output[0]) {
+            assert!((x - y).abs() <= 24.0f32.exp2().recip());
+        }
+    }
+
+    #[test]
+    fn stereo_channels_are_processed_independently() {
+        let input = test_signal(2, 4096);
+        let mut output = input.clone();
+        let mut engine = CrusherEngine::new(2);
+        engine.set_bit_depth(4.0);
+        engine.set_redux(4);
+        engine.reset();
+        process(&mut engine, &mut output);
+
+        for (input, output) in input.iter().zip(&output) {
+            // Every hold interval starts with the crushed input sample for that channel
+            for (i, y) in output.iter().enumerate().step_by(4) {
+                assert_eq!(*y, quantize(input[i], 1.0 / 16.0, QuantizerMode::Truncate));
+                assert_eq!(output[i + 1..i + 4], [*y; 3]);
+            }
+        }
+        assert_ne!(output[0], output[1]);
+    }
+
+    #[test]
+    fn extra_channels_are_left_alone() {
+        let input = test_signal(2, 512);
+        let mut output = input.clone();
+        let mut engine = CrusherEngine::new(1);
+        engine.set_bit_depth(2.0);
+        engine.reset();
+        process(&mut engine, &mut output);
+
+        assert_eq!(engine.num_channels(), 1);
+        assert_ne!(output[0], input[0]);
+        assert_eq!(output[1], input[1]);
+    }
+
+    #[test]
+    fn reset_snaps_to_the_new_parameter_values() {
+        let configure = |engine: &mut CrusherEngine| {
+            engine.set_bit_depth(5.0);
+            engine.set_gain(0.5);
+            engine.set_mix(0.8);
+            engine.set_clip_threshold(0.4);
+        };
+
+        let mut expected = test_signal(2, 2048);
+        let mut fresh = CrusherEngine::new(2);
+        configure(&mut fresh);
+        fresh.reset();
+        process(&mut fresh, &mut expected);
+
+        // Changing the parameters right before a reset shouldn't ramp from the old values
+        let mut engine = CrusherEngine::new(2);
+        engine.set_bit_depth(12.0);
+        process(&mut engine, &mut test_signal(2, 512));
+        configure(&mut engine);
+        engine.reset();
+        let mut output = test_signal(2, 2048);
+        process(&mut engine, &mut output);
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn switching_oversampling_does_not_replay_old_audio() {
+        let mut engine = CrusherEngine::new(2);
+        engine.set_bit_depth(8.0);
+        engine.set_mix(0.5);
+        for (from, to) in [
+            (Oversampling::X8, Oversampling::X2),
+            (Oversampling::X2, Oversampling::X8),
+            (Oversampling::X4, Oversampling::Off),
+        ] {
+            engine.set_oversampling(from);
+            process(&mut engine, &mut test_signal(2, 1024));
+            engine.set_oversampling(to);
+            let mut silence = vec![vec![0.0; 256]; 2];
+            process(&mut engine, &mut silence);
+
+            assert!(
+                silence.concat().iter().all(|sample| *sample == 0.0),
+                "{from:?} -> {to:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dry_signal_lines_up_with_the_reconstruction_delay() {
+        // Linear and Catmull-Rom interpolation reproduce a ramp exactly, so the processed signal
+        // is the input delayed by one or two hold intervals
+        let input: Vec<f32> = (0..2048).map(|i| i as f32 / 4096.0).collect();
+        for (redux_mode, reconstruction, delay) in [
+            (ReduxMode::Divide, Reconstruction::ZeroOrderHold, 0),
+            (ReduxMode::Divide, Reconstruction::Linear, 4),
+            (ReduxMode::Divide, Reconstruction::Cubic, 8),
+            (ReduxMode::Frequency, Reconstruction::Linear, 4),
+            (ReduxMode::Frequency, Reconstruction::Cubic, 8),
+        ] {
+            let render = |mix: f32| {
+                let mut engine = CrusherEngine::new(1);
+                engine.set_redux_mode(redux_mode);
+                engine.set_redux(4);
+                engine.set_redux_hz(44100.0 / 4.0);
+                engine.set_reconstruction(reconstruction);
+                engine.set_oversampling(Oversampling::X2);
+                engine.set_mix(mix);
+                engine.reset();
+
+                let mut output = vec![input.clone()];
+                process(&mut engine, &mut output);
+                output.remove(0)
+            };
+            let (wet, dry) = (render(1.0), render(0.0));
+
+            let delay = delay + Oversampling::X2.latency_samples() as usize;
+            for i in (delay + 64)..input.len() {
+                assert_eq!(
+                    dry[i],
+                    input[i - delay],
+                    "{redux_mode:?} {reconstruction:?}"
+                );
+                // The staircase is only right on the first sample of every hold interval
+                if reconstruction != Reconstruction::ZeroOrderHold || i % 4 == 0 {
+                    assert!(
+                        (wet[i] - dry[i]).abs() < 1e-4,
+                        "{redux_mode:?} {reconstruction:?}: {} != {}",
+                        wet[i],
+                        dry[i]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_does_not_hold_without_reduction() {
+        for redux_mode in [ReduxMode::Divide, ReduxMode::Frequency] {
+            let input = test_signal(1, 4096);
+            let mut output = input.clone();
+            let mut engine = CrusherEngine::with_seed(1, 20);
+            engine.set_redux_mode(redux_mode);
+            engine.set_redux(1);
+            engine.set_redux_hz(44100.0);
+            engine.set_redux_jitter(1.0);
+            engine.reset();
+            process(&mut engine, &mut output);
+
+            let unreduced = {
+                let mut output = input.clone();
+                let mut engine = CrusherEngine::new(1);
+                engine.reset();
+                process(&mut engine, &mut output);
+                output
+            };
+            assert_eq!(output, unreduced, "{redux_mode:?}");
+        }
+    }
+
+    #[test]
+    fn reset_clears_the_running_state() {
+        let mut engine = CrusherEngine::new(2);
+        engine.set_bit_depth(6.0);
+        engine.set_redux(7);
+        engine.set_noise_shaping(NoiseShaping::FirstOrder);
+        engine.reset();
+
+        let mut expected = test_signal(2, 2048);
+        let mut fresh = CrusherEngine::new(2);
+        fresh.set_bit_depth(6.0);
+        fresh.set_redux(7);
+        fresh.set_noise_shaping(NoiseShaping::FirstOrder);
+        fresh.reset();
+        process(&mut fresh, &mut expected);
+
+        // Leave the engine in the middle of a hold interval with some noise shaper state
+        process(&mut engine, &mut test_signal(2, 1001));
+        engine.reset();
+        let mut output = test_signal(2, 2048);
+        process(&mut engine, &mut output);
+
+        assert_eq!(output, expected);
+    }
+
+    /// Render `input` in blocks of `block_size` samples, calling the setters before every block
+    /// like the plugin does. The parameters change halfway through.
+    fn render_in_blocks(input: &[Vec<f32>], block_size: usize, redux_mode: ReduxMode) -> Vec<f32> {
+        let num_samples = input[0].len();
+        let mut output = input.to_vec();
+        let mut engine = CrusherEngine::with_seed(2, 1234);
+        engine.set_redux_mode(redux_mode);
+        engine.set_redux_phase(ReduxPhase::Independent);
+        engine.set_dither_mode(DitherMode::Triangular);
+        engine.set_entropy(0.5);
+
+        for start in (0..num_samples).step_by(block_size) {
+            let end = (start + block_size).min(num_samples);
+            let second_half = start >= num_samples / 2;
+            engine.set_bit_depth(if second_half { 6.0 } else { 10.5 });
+            engine.set_redux(if second_half { 5 } else { 7 });
+            engine.set_redux_hz(if second_half { 3000.0 } else { 7000.0 });
+            engine.set_gain(if second_half { 0.5 } else { 1.0 });
+            engine.set_mix(if second_half { 0.7 } else { 1.0 });
+
+            let mut block: Vec<&mut [f32]> = output
+                .iter_mut()
+                .map(|channel| &mut channel[start..end])
+                .collect();
+            engine.process_block(&mut block);
+        }
+
+        output.concat()
+    }
+
+    #[test]
+    fn output_does_not_depend_on_the_block_size() {
+        // The parameter change lands on a block boundary for every block size
+        let input = test_signal(2, 8192);
+        for redux_mode in [ReduxMode::Divide, ReduxMode::Frequency] {
+            let expected = render_in_blocks(&input, 32, redux_mode);
+            for block_size in [64, 512, 1024] {
+                assert!(
+                    render_in_blocks(&input, block_size, redux_mode) == expected,
+                    "{redux_mode:?} output differs at a block size of {block_size}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn noise_shaping_stays_bounded_at_low_bit_depths() {
+        for noise_shaping in [
+            NoiseShaping::FirstOrder,
+            NoiseShaping::FWeighted,
+            NoiseShaping::Inverse,
+        ] {
+            let mut engine = CrusherEngine::with_seed(2, 99);
+            engine.set_bit_depth(MIN_BIT_DEPTH);
+            engine.set_noise_shaping(noise_shaping);
+            engine.set_dither_mode(DitherMode::Triangular);
+            engine.reset();
+
+            let mut output = test_signal(2, 8192);
+            process(&mut engine, &mut output);
+            // Full scale plus at most one quantization step
+            assert!(output.concat().iter().all(|sample| sample.abs() <= 1.5));
+        }
+    }
+
+    #[test]
+    fn frequency_redux_follows_the_sample_rate() {
+        for (sample_rate, hold_length) in [(44100.0, 4), (88200.0, 8)] {
+            let mut engine = CrusherEngine::new(1);
+            engine.set_sample_rate(sample_rate);
+            engine.set_redux_mode(ReduxMode::Frequency);
+            engine.set_redux_hz(11025.0);
+            assert_eq!(engine.sample_rate(), sample_rate);
+
+            let mut output = test_signal(1, 1024);
+            process(&mut engine, &mut output);
+            for hold in output[0].chunks(hold_length) {
+                assert!(hold.iter().all(|sample| *sample == hold[0]));
+            }
+        }
+    }
+
+    /// Run a sine sweep from well above the reduced Nyquist frequency at a Redux factor of 8 up to
+    /// the host's Nyquist frequency through the engine, and return how much of its energy got
+    /// aliased down below the reduced Nyquist frequency, in decibels relative to the input.
+    fn alias_energy(anti_aliasing: AntiAliasing) -> f64 {
+        const LENGTH: usize = 4096;
+        let mut phase = 0.0f64;
+        let input: Vec<f32> = (0..LENGTH)
+            .map(|i| {
+                let t = i as f64 / LENGTH as f64;
+                phase += 0.1 + (0.35 * t);
+                // The window keeps the sweep's edges from smearing energy across the spectrum
+                let window = (std::f64::consts::PI * t).sin().powi(2);
+                (0.5 * window * (std::f64::consts::TAU * phase).sin()) as f32
+            })
+            .collect();
+
+        let mut engine = CrusherEngine::new(1);
+        engine.set_redux(8);
+        engine.set_anti_aliasing(anti_aliasing);
+        engine.reset();
+        let mut output = vec![input.clone()];
+        process(&mut engine, &mut output);
+
+        let aliased = band_energy(&output[0], 0.0, 1.0 / 16.0);
+        let total = band_energy(&input, 0.0, 0.5);
+        10.0 * (aliased / total).log10()
+    }
+
+    #[test]
+    fn anti_aliasing_removes_aliases_from_a_sweep() {
+        // Without the filter the whole sweep folds down into the reduced band
+        let off = alias_energy(AntiAliasing::Off);
+        let slope24 = alias_energy(AntiAliasing::Slope24);
+        let slope48 = alias_energy(AntiAliasing::Slope48);
+        assert!(off > -6.0, "{off}");
+        assert!(slope24 < -40.0, "{slope24}");
+        assert!(slope48 < slope24 - 20.0, "{slope48}");
+    }
+
+    /// Crush a high sine to 4 bits and return the energy that didn't end up on one of its
+    /// harmonics, in decibels relative to the whole output. Harmonics above the Nyquist frequency
+    /// fold back in between the harmonics below it.
+    fn inharmonic_energy(oversampling: Oversampling) -> f64 {
+        const LENGTH: usize = 4096;
+        // The sine completes a whole number of periods, and harmonics only fall on other
+        // harmonics' bins when they fold back
+        const BIN: usize = 371;
+
+        let mut engine = CrusherEngine::new(1);
+        engine.set_bit_depth(4.0);
+        engine.set_oversampling(oversampling);
+        engine.reset();
+        let latency = engine.latency_samples() as usize;
+        let mut output = vec![(0..LENGTH + latency)
+            .map(|i| {
+                let phase = (i * BIN) as f64 / LENGTH as f64;
+                (0.9 * (std::f64::consts::TAU * phase).sin()) as f32
+            })
+            .collect()];
+        process(&mut engine, &mut output);
+
+        let output = &output[0][latency..];
+        let total = band_energy(output, 0.0, 0.5);
+        let harmonics: f64 = (1..)
+            .map(|harmonic| harmonic * BIN)
+            .take_while(|&bin| bin < LENGTH / 2)
+            .map(|bin| {
+                let frequency = bin as f64 / LENGTH as f64;
+                band_energy(output, frequency, frequency)
+            })
+            .sum();
+        10.0 * ((total - harmonics) / total).log10()
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        let off = inharmonic_energy(Oversampling::Off);
+        let mut previous = off;
+        for oversampling in [Oversampling::X2, Oversampling::X4, Oversampling::X8] {
+            let energy = inharmonic_energy(oversampling);
+            assert!(energy < previous - 1.0, "{oversampling:?}: {energy} dB");
+            previous = energy;
+        }
+        assert!(previous < off - 6.0, "{off} dB -> {previous} dB");
+    }
+}
@@ -0,0 +1,101 @@
+use nih_plug::prelude::*;
+
+use super::biquad::{butterworth_q, Biquad};
+
+/// The most biquad sections [`AntiAliasing`] can use.
+const MAX_SECTIONS: usize = 4;
+
+/// The cutoff relative to the reduced sample rate. This sits a bit below the reduced Nyquist
+/// frequency so the steeper slopes have room to roll off.
+const CUTOFF_RATIO: f32 = 0.45;
+
+/// The slope of the low-pass filter in front of the Redux stage.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasing {
+    /// No filtering, so everything above the reduced Nyquist frequency aliases.
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    #[id = "12db"]
+    #[name = "12 dB/oct"]
+    Slope12,
+    #[id = "24db"]
+    #[name = "24 dB/oct"]
+    Slope24,
+    #[id = "48db"]
+    #[name = "48 dB/oct"]
+    Slope48,
+}
+
+impl AntiAliasing {
+    /// The number of biquad sections in the Butterworth cascade.
+    fn num_sections(self) -> usize {
+        match self {
+            AntiAliasing::Off => 0,
+            AntiAliasing::Slope12 => 1,
+            AntiAliasing::Slope24 => 2,
+            AntiAliasing::Slope48 => 4,
+        }
+    }
+}
+
+/// A Butterworth low-pass filter with a cutoff that tracks the Redux rate, so the sample-and-hold
+/// behaves like a sampler with a proper band-limiting input filter.
+pub struct AntiAliasingFilter {
+    /// The slope and cutoff the coefficients were last computed for.
+    slope: AntiAliasing,
+    cutoff: f32,
+    sections: [Biquad; MAX_SECTIONS],
+    /// The filter state for every channel and biquad section.
+    states: Vec<[[f32; 2]; MAX_SECTIONS]>,
+}
+
+impl AntiAliasingFilter {
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            slope: AntiAliasing::Off,
+            cutoff: 0.0,
+            sections: [Biquad::default(); MAX_SECTIONS],
+            states: vec![[[0.0; 2]; MAX_SECTIONS]; num_channels],
+        }
+    }
+
+    /// Resize the per-channel state. This allocates.
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        self.states.resize(num_channels, [[0.0; 2]; MAX_SECTIONS]);
+    }
+
+    pub fn reset(&mut self) {
+        for state in &mut self.states {
+            *state = [[0.0; 2]; MAX_SECTIONS];
+        }
+    }
+
+    /// Update the filter for a new slope or reduced sample rate. `reduced_rate` is the Redux rate
+    /// divided by the host's sample rate. The coefficients are only recomputed when something
+    /// changed, so this is cheap to call for every sample.
+    pub fn update(&mut self, slope: AntiAliasing, reduced_rate: f32) {
+        let cutoff = (reduced_rate * CUTOFF_RATIO).min(CUTOFF_RATIO);
+        if slope == self.slope && cutoff == self.cutoff {
+            return;
+        }
+
+        self.slope = slope;
+        self.cutoff = cutoff;
+        let order = slope.num_sections() * 2;
+        for (i, section) in self.sections[..slope.num_sections()].iter_mut().enumerate() {
+            *section = Biquad::lowpass(cutoff, butterworth_q(order, i));
+        }
+    }
+
+    /// Filter a single sample for `channel`.
+    pub fn process(&mut self, channel: usize, sample: f32) -> f32 {
+        let num_sections = self.slope.num_sections();
+        self.sections[..num_sections]
+            .iter()
+            .zip(self.states[channel].iter_mut())
+            .fold(sample, |sample, (section, state)| {
+                section.process(state, sample)
+            })
+    }
+}
@@ -0,0 +1,62 @@
+use std::f32::consts::PI;
+
+/// The coefficients for a single biquad section, normalized so `a0` is `1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Default for Biquad {
+    /// A biquad that passes the signal through unchanged.
+    fn default() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+}
+
+impl Biquad {
+    /// A low-pass filter from the RBJ cookbook. `cutoff` is the cutoff frequency divided by the
+    /// sample rate, and needs to be below `0.5`.
+    pub fn lowpass(cutoff: f32, q: f32) -> Self {
+        let omega = 2.0 * PI * cutoff;
+        let (sin, cos) = omega.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        let b1 = (1.0 - cos) / a0;
+        Self {
+            b0: b1 / 2.0,
+            b1,
+            b2: b1 / 2.0,
+            a1: (-2.0 * cos) / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// Process a single sample. `state` holds the filter's two delay elements in the transposed
+    /// direct form II.
+    pub fn process(&self, state: &mut [f32; 2], sample: f32) -> f32 {
+        let output = (self.b0 * sample) + state[0];
+        state[0] = (self.b1 * sample) - (self.a1 * output) + state[1];
+        state[1] = (self.b2 * sample) - (self.a2 * output);
+
+        output
+    }
+}
+
+/// The Q values for the biquad sections making up an `order`th order Butterworth low-pass filter.
+/// Only even orders are supported.
+pub fn butterworth_q(order: usize, section: usize) -> f32 {
+    let angle = ((2 * section + 1) as f32 * PI) / (2 * order) as f32;
+
+    1.0 / (2.0 * angle.cos())
+}
@@ -0,0 +1,126 @@
+use rand::Rng;
+
+use super::bit_mangler::{from_word, to_word, word_width};
+
+/// Randomly flips bits in the quantized word, like a scratched CD or a noisy transmission line.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BitErrors {
+    /// The average probability of any single bit getting flipped, per sample.
+    pub rate: f32,
+    /// How the errors are spread over the word. `-1.0` puts all of the errors in the LSBs, `0.0`
+    /// spreads them out evenly, and `1.0` puts all of them in the MSBs.
+    pub weighting: f32,
+}
+
+impl BitErrors {
+    pub fn is_active(&self) -> bool {
+        self.rate > 0.0
+    }
+
+    /// The probability of a bit getting flipped, where `position` goes from `0.0` for the LSB to
+    /// `1.0` for the MSB. This averages out to [`rate`][Self::rate] over the whole word.
+    pub fn bit_probability(&self, position: f32) -> f32 {
+        let weight = 1.0 + self.weighting.clamp(-1.0, 1.0) * (2.0 * position - 1.0);
+
+        (self.rate * weight).clamp(0.0, 1.0)
+    }
+
+    /// Convert `sample` to a word on the Crush stage's grid at `bit_depth`, flip some of its bits,
+    /// and convert it back to a float. The sign bit counts as the MSB.
+    pub fn process(&self, sample: f32, bit_depth: u32, rng: &mut impl Rng) -> f32 {
+        let width = word_width(bit_depth);
+        let mut word = to_word(sample, bit_depth);
+        for bit in 0..width {
+            let position = bit as f32 / (width - 1) as f32;
+            if rng.gen::<f32>() < self.bit_probability(position) {
+                word ^= 1 << bit;
+            }
+        }
+
+        from_word(word, bit_depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    const BIT_DEPTH: u32 = 8;
+    const NUM_SAMPLES: usize = 20_000;
+
+    fn render(errors: &BitErrors, seed: u64) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..NUM_SAMPLES)
+            .map(|i| errors.process((i as f32 * 0.01).sin() * 0.5, BIT_DEPTH, &mut rng))
+            .collect()
+    }
+
+    /// How often each bit of the word got flipped, LSB first.
+    fn flip_rates(errors: &BitErrors) -> Vec<f32> {
+        let width = word_width(BIT_DEPTH);
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut flips = vec![0usize; width as usize];
+        for _ in 0..NUM_SAMPLES {
+            let word = to_word(errors.process(0.0, BIT_DEPTH, &mut rng), BIT_DEPTH);
+            for (bit, count) in flips.iter_mut().enumerate() {
+                *count += (word >> bit) as usize & 1;
+            }
+        }
+
+        flips
+            .into_iter()
+            .map(|count| count as f32 / NUM_SAMPLES as f32)
+            .collect()
+    }
+
+    /// Check the flip rates against [`BitErrors::bit_probability()`] and return them.
+    fn assert_flip_rates(weighting: f32) -> Vec<f32> {
+        let errors = BitErrors {
+            rate: 0.1,
+            weighting,
+        };
+        let rates = flip_rates(&errors);
+        let width = rates.len();
+        for (bit, &rate) in rates.iter().enumerate() {
+            let expected = errors.bit_probability(bit as f32 / (width - 1) as f32);
+            assert!(
+                (rate - expected).abs() < 0.01,
+                "weighting {weighting}, bit {bit}: {rate} != {expected}"
+            );
+        }
+
+        rates
+    }
+
+    #[test]
+    fn seeded_errors_are_reproducible() {
+        let errors = BitErrors {
+            rate: 0.05,
+            weighting: 0.0,
+        };
+
+        assert_eq!(render(&errors, 1), render(&errors, 1));
+        assert_ne!(render(&errors, 1), render(&errors, 2));
+    }
+
+    #[test]
+    fn even_weighting_flips_every_bit_equally() {
+        let rates = assert_flip_rates(0.0);
+        assert!(rates.iter().all(|&rate| (rate - 0.1).abs() < 0.01));
+    }
+
+    #[test]
+    fn lsb_weighting_flips_the_low_bits() {
+        let rates = assert_flip_rates(-1.0);
+        assert_eq!(*rates.last().unwrap(), 0.0);
+        assert!(rates[0] > 0.19);
+    }
+
+    #[test]
+    fn msb_weighting_flips_the_high_bits() {
+        let rates = assert_flip_rates(1.0);
+        assert_eq!(rates[0], 0.0);
+        assert!(*rates.last().unwrap() > 0.19);
+    }
+}
@@ -0,0 +1,188 @@
+use nih_plug::prelude::*;
+
+/// The highest bit depth the bit mangler works on, and the width of its masks. Masks are stored MSB
+/// first, so the first bit of a mask always refers to the sign bit regardless of the current bit
+/// depth. Words have a sign bit on top of their bit depth (see [`word_width()`]), so at the highest
+/// bit depth the word's LSB falls outside of the masks and is left alone.
+pub const MAX_WORD_BITS: u32 = 24;
+
+/// What the bit mangler's shift amount does.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitShiftMode {
+    /// Rotate the word, so bits shifted out on one end come back in on the other end.
+    #[id = "rotate"]
+    #[name = "Rotate"]
+    Rotate,
+    /// Shift the word. Left shifts wrap around, right shifts are arithmetic.
+    #[id = "shift"]
+    #[name = "Shift"]
+    Shift,
+}
+
+/// Converts samples to two's complement integer words, messes with their bits, and converts them
+/// back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitMangler {
+    /// Bits that are cleared in this mask get cleared in the word. MSB first, see
+    /// [`MAX_WORD_BITS`].
+    pub and_mask: u32,
+    /// Bits that are set in this mask get flipped in the word. MSB first, see [`MAX_WORD_BITS`].
+    pub xor_mask: u32,
+    /// Positive values shift or rotate toward the MSB, negative values toward the LSB.
+    pub shift: i32,
+    pub shift_mode: BitShiftMode,
+}
+
+impl Default for BitMangler {
+    fn default() -> Self {
+        Self {
+            and_mask: (1 << MAX_WORD_BITS) - 1,
+            xor_mask: 0,
+            shift: 0,
+            shift_mode: BitShiftMode::Rotate,
+        }
+    }
+}
+
+impl BitMangler {
+    /// Whether this would leave the signal untouched, apart from rounding it to the word size.
+    pub fn is_identity(&self) -> bool {
+        let full_mask = (1 << MAX_WORD_BITS) - 1;
+
+        self.and_mask & full_mask == full_mask && self.xor_mask & full_mask == 0 && self.shift == 0
+    }
+
+    /// Convert `sample` to a word on the Crush stage's grid at `bit_depth`, apply the masks and the
+    /// shift, and convert it back to a float.
+    pub fn process(&self, sample: f32, bit_depth: u32) -> f32 {
+        let width = word_width(bit_depth);
+        let full_mask = (1u32 << width) - 1;
+        let mut word = to_word(sample, bit_depth);
+
+        word &= align_mask(self.and_mask, width, 1);
+        word ^= align_mask(self.xor_mask, width, 0);
+        word = match self.shift_mode {
+            BitShiftMode::Rotate => {
+                let amount = self.shift.rem_euclid(width as i32) as u32;
+                ((word << amount) | (word >> (width - amount))) & full_mask
+            }
+            BitShiftMode::Shift if self.shift >= 0 => {
+                word.wrapping_shl(self.shift as u32) & full_mask
+            }
+            BitShiftMode::Shift => {
+                let amount = self.shift.unsigned_abs().min(width - 1);
+                (sign_extend(word, width) >> amount) as u32 & full_mask
+            }
+        };
+
+        from_word(word, bit_depth)
+    }
+}
+
+/// The number of bits in a word at `bit_depth`. The Crush stage quantizes `[-1, 1]` in steps of
+/// `2^-bit_depth`, so a word needs `bit_depth` bits for the magnitude plus a sign bit.
+pub(super) fn word_width(bit_depth: u32) -> u32 {
+    bit_depth.clamp(1, MAX_WORD_BITS) + 1
+}
+
+/// Round `sample` to a multiple of the Crush stage's step size at `bit_depth`, and return that
+/// multiple as a two's complement integer in the lower [`word_width()`] bits. Samples that are
+/// already on the grid round trip through [`from_word()`] exactly, apart from `1.0` which is one
+/// step past the largest word and gets clamped.
+pub(super) fn to_word(sample: f32, bit_depth: u32) -> u32 {
+    let width = word_width(bit_depth);
+    let scale = ((width - 1) as f32).exp2();
+    let min = -(1i32 << (width - 1));
+    let max = (1i32 << (width - 1)) - 1;
+
+    ((sample * scale).round() as i32).clamp(min, max) as u32 & ((1u32 << width) - 1)
+}
+
+/// The inverse of [`to_word()`].
+pub(super) fn from_word(word: u32, bit_depth: u32) -> f32 {
+    let width = word_width(bit_depth);
+    let scale = ((width - 1) as f32).exp2();
+
+    sign_extend(word, width) as f32 / scale
+}
+
+/// Line an MSB first [`MAX_WORD_BITS`] wide mask up with the top of a `width` bit word. At the
+/// highest bit depth the word is one bit wider than the mask, and its LSB gets `fill`.
+fn align_mask(mask: u32, width: u32, fill: u32) -> u32 {
+    if width > MAX_WORD_BITS {
+        (mask << (width - MAX_WORD_BITS)) | fill
+    } else {
+        mask >> (MAX_WORD_BITS - width)
+    }
+}
+
+/// Interpret the lower `word_bits` bits of `word` as a two's complement integer.
+fn sign_extend(word: u32, word_bits: u32) -> i32 {
+    let unused_bits = 32 - word_bits;
+
+    ((word << unused_bits) as i32) >> unused_bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::quantizer::{quantize, QuantizerMode};
+
+    #[test]
+    fn words_use_the_crush_grid() {
+        for bit_depth in 1..=MAX_WORD_BITS {
+            let step = (-(bit_depth as f32)).exp2();
+            for sample in [-1.0, -0.7, -0.3, -step, 0.0, step, 0.3, 0.7] {
+                let crushed = quantize(sample, step, QuantizerMode::Round);
+                assert_eq!(
+                    from_word(to_word(crushed, bit_depth), bit_depth),
+                    crushed,
+                    "{bit_depth} bits, {sample}"
+                );
+            }
+
+            // `1.0` is one step past the largest word
+            assert_eq!(from_word(to_word(1.0, bit_depth), bit_depth), 1.0 - step);
+        }
+    }
+
+    #[test]
+    fn identity_leaves_crushed_samples_alone() {
+        let mangler = BitMangler::default();
+        assert!(mangler.is_identity());
+        for bit_depth in 1..=MAX_WORD_BITS {
+            let step = (-(bit_depth as f32)).exp2();
+            let crushed = quantize(-0.3, step, QuantizerMode::Round);
+            assert_eq!(mangler.process(crushed, bit_depth), crushed);
+        }
+    }
+
+    #[test]
+    fn masks_line_up_with_the_sign_bit_and_the_lsb() {
+        let flip_sign = BitMangler {
+            xor_mask: 1 << (MAX_WORD_BITS - 1),
+            ..BitMangler::default()
+        };
+        for bit_depth in 1..=MAX_WORD_BITS {
+            let step = (-(bit_depth as f32)).exp2();
+            // Flipping the sign bit of a two's complement word moves it by a full `2.0`
+            assert_eq!(flip_sign.process(0.5, bit_depth), -0.5, "{bit_depth} bits");
+            // The word's LSB is one Crush step
+            if bit_depth < MAX_WORD_BITS {
+                let flip_lsb = BitMangler {
+                    xor_mask: 1 << (MAX_WORD_BITS - word_width(bit_depth)),
+                    ..BitMangler::default()
+                };
+                assert_eq!(flip_lsb.process(0.0, bit_depth), step, "{bit_depth} bits");
+            }
+        }
+
+        // At the highest bit depth the last mask bit is the second to last bit of the word
+        let flip_last = BitMangler {
+            xor_mask: 1,
+            ..BitMangler::default()
+        };
+        let step = (-(MAX_WORD_BITS as f32)).exp2();
+        assert_eq!(flip_last.process(0.0, MAX_WORD_BITS), 2.0 * step);
+    }
+}
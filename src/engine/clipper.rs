@@ -0,0 +1,122 @@
+use nih_plug::prelude::*;
+
+/// The transfer curve used by the Clip stage.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipMode {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    /// Anything past the threshold gets flattened.
+    #[id = "hard"]
+    #[name = "Hard"]
+    Hard,
+    /// A `tanh()` curve that approaches the threshold without reaching it, until the input gets so
+    /// loud that `tanh()` rounds to one.
+    #[id = "soft"]
+    #[name = "Soft"]
+    Soft,
+    /// Anything past the threshold gets mirrored back down, over and over again for very loud
+    /// signals.
+    #[id = "foldback"]
+    #[name = "Foldback"]
+    Foldback,
+    /// Anything past the threshold wraps around to the other side, like an integer overflowing.
+    #[id = "wrap"]
+    #[name = "Wrap"]
+    Wrap,
+}
+
+/// Where in the signal chain the Clip stage sits.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipPlacement {
+    /// Before the Crush stage, so the clipped signal gets crushed.
+    #[id = "pre_crush"]
+    #[name = "Pre-Crush"]
+    PreCrush,
+    /// After the Redux stage and the output gain, so the gain drives the clipper.
+    #[id = "post_crush"]
+    #[name = "Post-Crush"]
+    PostCrush,
+}
+
+/// Clip a single sample at `threshold`, which should be a positive linear gain.
+pub fn clip(sample: f32, threshold: f32, mode: ClipMode) -> f32 {
+    match mode {
+        ClipMode::Off => sample,
+        ClipMode::Hard => sample.clamp(-threshold, threshold),
+        ClipMode::Soft => threshold * (sample / threshold).tanh(),
+        ClipMode::Foldback => {
+            // A triangle wave with a period of four times the threshold that matches the input
+            // between the two thresholds
+            let phase = ((sample / threshold) + 1.0).rem_euclid(4.0);
+            threshold * (1.0 - (phase - 2.0).abs())
+        }
+        ClipMode::Wrap => (sample + threshold).rem_euclid(2.0 * threshold) - threshold,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs at, between and beyond the thresholds, including more than one threshold past them.
+    /// All of these and the expected values are exact in binary.
+    const INPUTS: [f32; 11] = [
+        -2.25, -1.25, -0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75, 1.25, 2.25,
+    ];
+    const THRESHOLD: f32 = 0.5;
+
+    fn transfer_curve(mode: ClipMode) -> Vec<f32> {
+        INPUTS
+            .iter()
+            .map(|&sample| clip(sample, THRESHOLD, mode))
+            .collect()
+    }
+
+    #[test]
+    fn off_passes_through() {
+        assert_eq!(transfer_curve(ClipMode::Off), INPUTS);
+    }
+
+    #[test]
+    fn hard_flattens_past_the_threshold() {
+        assert_eq!(
+            transfer_curve(ClipMode::Hard),
+            [-0.5, -0.5, -0.5, -0.5, -0.25, 0.0, 0.25, 0.5, 0.5, 0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn soft_stays_below_the_threshold() {
+        for (sample, expected) in [(0.5, 0.380_797_1), (1.0, 0.482_013_8), (2.0, 0.499_664_6)] {
+            let clipped = clip(sample, THRESHOLD, ClipMode::Soft);
+            assert!((clipped - expected).abs() < 1e-6, "{sample}: {clipped}");
+            assert!(clipped < THRESHOLD, "{sample}: {clipped}");
+            assert_eq!(clip(-sample, THRESHOLD, ClipMode::Soft), -clipped);
+        }
+
+        // Small signals are left mostly alone
+        assert!((clip(0.01, THRESHOLD, ClipMode::Soft) - 0.01).abs() < 1e-5);
+        // And even extremely loud signals don't get past the threshold
+        assert!(clip(1000.0, THRESHOLD, ClipMode::Soft) <= THRESHOLD);
+    }
+
+    #[test]
+    fn foldback_mirrors_back_repeatedly() {
+        // 0.75 and 1.25 fold back down from the upper threshold, and 2.25 has gone on to fold back
+        // up from the lower threshold as well
+        assert_eq!(
+            transfer_curve(ClipMode::Foldback),
+            [-0.25, 0.25, -0.25, -0.5, -0.25, 0.0, 0.25, 0.5, 0.25, -0.25, 0.25]
+        );
+    }
+
+    #[test]
+    fn wrap_wraps_to_the_other_side() {
+        // The positive threshold itself already wraps around to the negative one
+        assert_eq!(
+            transfer_curve(ClipMode::Wrap),
+            [-0.25, -0.25, 0.25, -0.5, -0.25, 0.0, 0.25, -0.5, -0.25, 0.25, 0.25]
+        );
+    }
+}
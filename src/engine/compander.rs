@@ -0,0 +1,39 @@
+//! G.711 style companding. Compressing before the quantizer and expanding afterwards spends more
+//! of the quantization levels on quiet signals, at the cost of coarser steps for loud signals.
+
+/// Compress `sample` using the mu-law curve. A `mu` of 255 matches G.711.
+pub fn mu_law_compress(sample: f32, mu: f32) -> f32 {
+    sample.signum() * (mu * sample.abs()).ln_1p() / mu.ln_1p()
+}
+
+/// The inverse of [`mu_law_compress()`].
+pub fn mu_law_expand(sample: f32, mu: f32) -> f32 {
+    sample.signum() * (sample.abs() * mu.ln_1p()).exp_m1() / mu
+}
+
+/// Compress `sample` using the A-law curve. An `a` of 87.6 matches G.711, and an `a` of 1 is
+/// linear.
+pub fn a_law_compress(sample: f32, a: f32) -> f32 {
+    let abs = sample.abs();
+    let denominator = 1.0 + a.ln();
+    let compressed = if abs < 1.0 / a {
+        a * abs / denominator
+    } else {
+        (1.0 + (a * abs).ln()) / denominator
+    };
+
+    sample.signum() * compressed
+}
+
+/// The inverse of [`a_law_compress()`].
+pub fn a_law_expand(sample: f32, a: f32) -> f32 {
+    let abs = sample.abs();
+    let denominator = 1.0 + a.ln();
+    let expanded = if abs < 1.0 / denominator {
+        abs * denominator / a
+    } else {
+        (abs * denominator - 1.0).exp() / a
+    };
+
+    sample.signum() * expanded
+}
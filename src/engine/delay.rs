@@ -0,0 +1,46 @@
+/// A multichannel delay line with a fixed maximum delay, used to keep the dry signal in line with
+/// the latency of the wet signal.
+pub struct DelayLine {
+    /// A ring buffer for every channel.
+    buffers: Vec<Vec<f32>>,
+    /// The next position in the ring buffers to write to.
+    write_pos: usize,
+}
+
+impl DelayLine {
+    pub fn new(num_channels: usize, max_delay: usize) -> Self {
+        Self {
+            buffers: vec![vec![0.0; max_delay + 1]; num_channels],
+            write_pos: 0,
+        }
+    }
+
+    /// Resize the ring buffers. This allocates.
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        let length = self.buffers.first().map_or(1, Vec::len);
+        self.buffers.resize_with(num_channels, || vec![0.0; length]);
+    }
+
+    pub fn reset(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.fill(0.0);
+        }
+        self.write_pos = 0;
+    }
+
+    /// Write a sample for `channel` and read back the sample from `delay` samples ago. The delay
+    /// is capped at the maximum delay.
+    pub fn process(&mut self, channel: usize, sample: f32, delay: usize) -> f32 {
+        let buffer = &mut self.buffers[channel];
+        buffer[self.write_pos] = sample;
+
+        let delay = delay.min(buffer.len() - 1);
+        buffer[(self.write_pos + buffer.len() - delay) % buffer.len()]
+    }
+
+    /// Called once at the end of every sample, after all channels have been processed.
+    pub fn end_sample(&mut self) {
+        let length = self.buffers.first().map_or(1, Vec::len);
+        self.write_pos = (self.write_pos + 1) % length;
+    }
+}
@@ -0,0 +1,65 @@
+use nih_plug::prelude::*;
+use rand::Rng;
+
+/// The probability density function of the dither noise added before quantization.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherMode {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    /// Rectangular PDF, one uniform random value spanning one step. Removes the distortion but
+    /// leaves noise modulation.
+    #[id = "rpdf"]
+    #[name = "RPDF"]
+    Rectangular,
+    /// Triangular PDF, the sum of two uniform random values spanning two steps. This also removes
+    /// the noise modulation, which is what you want for proper word length reduction.
+    #[id = "tpdf"]
+    #[name = "TPDF"]
+    Triangular,
+    /// Triangular PDF made by differencing successive uniform random values, which pushes most of
+    /// the dither noise up toward Nyquist.
+    #[id = "hp_tpdf"]
+    #[name = "HP TPDF"]
+    HighPassTriangular,
+}
+
+/// Generates dither noise, in quantization steps.
+pub struct Dither {
+    /// The previous uniform random value for every channel, used for
+    /// [`DitherMode::HighPassTriangular`].
+    previous: Vec<f32>,
+}
+
+impl Dither {
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            previous: vec![0.0; num_channels],
+        }
+    }
+
+    /// Resize the per-channel state. This allocates.
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        self.previous.resize(num_channels, 0.0);
+    }
+
+    pub fn reset(&mut self) {
+        self.previous.fill(0.0);
+    }
+
+    /// Get the next dither value for `channel`. The result should be multiplied by the
+    /// quantization step size before adding it to the signal.
+    pub fn next(&mut self, channel: usize, mode: DitherMode, rng: &mut impl Rng) -> f32 {
+        match mode {
+            DitherMode::Off => 0.0,
+            DitherMode::Rectangular => rng.gen::<f32>() - 0.5,
+            DitherMode::Triangular => rng.gen::<f32>() - rng.gen::<f32>(),
+            DitherMode::HighPassTriangular => {
+                let current = rng.gen::<f32>();
+                let previous = std::mem::replace(&mut self.previous[channel], current);
+
+                current - previous
+            }
+        }
+    }
+}
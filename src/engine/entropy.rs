@@ -0,0 +1,324 @@
+//! Turns the Entropy amount and random values into modulation of the other stages.
+
+use nih_plug::prelude::*;
+
+/// The lowest bit depth Entropy can push the Crush stage to. Zero bits would leave a single
+/// quantization level, which turns the quantizer into a gate.
+pub const MIN_BIT_DEPTH: f32 = 1.0;
+
+/// Lower `bit_depth` by a random amount. `amount` is the Entropy amount between `0.0` and `1.0`,
+/// and `random` is a random value between `0.0` and `1.0`.
+///
+/// The result always lies within `[floor, bit_depth]` (or is `bit_depth` if that's already below
+/// `floor`). With a uniformly distributed `random` the result is uniformly distributed over the
+/// top `amount` fraction of that range. So at 0% Entropy the bit depth is left alone, at 50% it
+/// lands anywhere in the upper half of the range with the mean a quarter of the way down, and at
+/// 100% it can land anywhere between the set bit depth and the floor.
+pub fn perturb_bit_depth(bit_depth: f32, amount: f32, floor: f32, random: f32) -> f32 {
+    let floor = floor.max(MIN_BIT_DEPTH).min(bit_depth);
+    let amount = amount.clamp(0.0, 1.0);
+    let random = random.clamp(0.0, 1.0);
+
+    bit_depth - (random * amount * (bit_depth - floor))
+}
+
+/// How often Entropy picks a new random value.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntropyRateMode {
+    /// A fixed rate in Hz.
+    #[id = "hz"]
+    #[name = "Hz"]
+    Hz,
+    /// A note division synced to the host's tempo.
+    #[id = "sync"]
+    #[name = "Sync"]
+    Sync,
+}
+
+/// Tempo synced intervals for [`EntropyRateMode::Sync`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteDivision {
+    #[id = "1_1"]
+    #[name = "1/1"]
+    Whole,
+    #[id = "1_2"]
+    #[name = "1/2"]
+    Half,
+    #[id = "1_4"]
+    #[name = "1/4"]
+    Quarter,
+    #[id = "1_8"]
+    #[name = "1/8"]
+    Eighth,
+    #[id = "1_8t"]
+    #[name = "1/8T"]
+    EighthTriplet,
+    #[id = "1_16"]
+    #[name = "1/16"]
+    Sixteenth,
+    #[id = "1_16t"]
+    #[name = "1/16T"]
+    SixteenthTriplet,
+    #[id = "1_32"]
+    #[name = "1/32"]
+    ThirtySecond,
+}
+
+impl NoteDivision {
+    /// The length of the division in quarter notes.
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::EighthTriplet => 1.0 / 3.0,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::SixteenthTriplet => 1.0 / 6.0,
+            NoteDivision::ThirtySecond => 0.125,
+        }
+    }
+
+    /// The rate in Hz this division corresponds to at `tempo` BPM.
+    pub fn rate_hz(self, tempo: f64) -> f32 {
+        (tempo / 60.0 / self.beats()) as f32
+    }
+
+    /// The length in milliseconds this division corresponds to at `tempo` BPM.
+    pub fn length_ms(self, tempo: f64) -> f32 {
+        (self.beats() * 60_000.0 / tempo) as f32
+    }
+}
+
+/// Picks a new random value at a fixed rate, optionally gliding from one
+/// value to the next. This runs at the sample level so the timing does not depend on the host's
+/// buffer size.
+#[derive(Default)]
+pub struct RandomClock {
+    /// The number of intervals since the last reset or the position set in
+    /// [`set_position()`][Self::set_position()].
+    interval: i64,
+    /// The position within the current interval, between `0.0` and `1.0`.
+    phase: f64,
+    /// The value the clock is gliding away from.
+    previous: f32,
+    /// The value the clock is gliding toward.
+    target: f32,
+    /// The last value returned from [`next()`][Self::next()], or `None` after a reset.
+    current: Option<f32>,
+    /// Set when the phase wraps around so the next call draws a new value.
+    needs_draw: bool,
+}
+
+impl RandomClock {
+    /// How far apart [`set_position()`][Self::set_position()] and the clock's own position can be
+    /// before the clock jumps, in intervals.
+    pub const SYNC_TOLERANCE: f64 = 1e-6;
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Move the clock to `position`, measured in intervals from a fixed point like the start of
+    /// the song. This lines the intervals up with the host's beat grid. A new value is drawn on the
+    /// next call if `position` is in another interval than the clock. Differences smaller than
+    /// [`SYNC_TOLERANCE`][Self::SYNC_TOLERANCE] are left alone, so rounding errors right at the
+    /// start of an interval don't draw a value twice.
+    pub fn set_position(&mut self, position: f64) {
+        let current = self.interval as f64 + self.phase;
+        if (position - current).abs() < Self::SYNC_TOLERANCE {
+            return;
+        }
+
+        let interval = position.floor();
+        if interval as i64 != self.interval {
+            self.needs_draw = true;
+        }
+        self.interval = interval as i64;
+        self.phase = position - interval;
+    }
+
+    /// The value drawn for the current interval, without any gliding.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Get the value for the current sample and advance the clock. `increment` is the rate divided
+    /// by the sample rate. `slew` is the fraction of an interval it takes to glide to a new value,
+    /// with `0.0` jumping right away. `draw` is called to get a new random value at the start of
+    /// every interval.
+    pub fn next(&mut self, increment: f64, slew: f32, draw: impl FnOnce() -> f32) -> f32 {
+        if self.needs_draw || self.current.is_none() {
+            self.needs_draw = false;
+            self.target = draw();
+            // There's nothing to glide from right after a reset
+            self.previous = self.current.unwrap_or(self.target);
+        }
+
+        let value = if slew > 0.0 {
+            let t = (self.phase as f32 / slew).min(1.0);
+            self.previous + ((self.target - self.previous) * t)
+        } else {
+            self.target
+        };
+        self.current = Some(value);
+
+        self.phase += increment;
+        if self.phase >= 1.0 {
+            self.interval += self.phase.floor() as i64;
+            self.phase = self.phase.fract();
+            self.needs_draw = true;
+        }
+
+        value
+    }
+}
+
+/// How far Entropy can push the Redux rate down, in octaves.
+pub const MAX_REDUX_OCTAVES: f32 = 4.0;
+/// How far Entropy can pull the output gain down, in decibels.
+pub const MAX_ATTENUATION_DB: f32 = 24.0;
+
+/// How strongly Entropy modulates each of its targets, all between `0.0` and `1.0`. The
+/// modulation amount for a target is the Entropy amount times the target's depth times the current
+/// random value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntropyDepths {
+    /// Lowers the bit depth, see [`perturb_bit_depth()`].
+    pub bit_depth: f32,
+    /// Lowers the Redux rate by up to [`MAX_REDUX_OCTAVES`].
+    pub redux: f32,
+    /// Lowers the output gain by up to [`MAX_ATTENUATION_DB`].
+    pub gain: f32,
+    /// Pulls the mix toward the dry signal.
+    pub mix: f32,
+    /// Raises the chance of a packet getting lost in the packet loss stage.
+    pub dropout: f32,
+    /// The chance of a slice getting stuttered.
+    pub stutter: f32,
+}
+
+impl Default for EntropyDepths {
+    fn default() -> Self {
+        Self {
+            bit_depth: 1.0,
+            redux: 0.0,
+            gain: 0.0,
+            mix: 0.0,
+            dropout: 0.0,
+            stutter: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    const BIT_DEPTH: f32 = 16.0;
+    const FLOOR: f32 = 2.0;
+
+    /// Perturb [`BIT_DEPTH`] with a lot of seeded uniform random values, returns the minimum,
+    /// maximum and mean.
+    fn statistics(amount: f32) -> (f32, f32, f32) {
+        let mut rng = StdRng::seed_from_u64(13);
+        let values: Vec<f32> = (0..10_000)
+            .map(|_| perturb_bit_depth(BIT_DEPTH, amount, FLOOR, rng.gen()))
+            .collect();
+
+        let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        (min, max, mean)
+    }
+
+    #[test]
+    fn no_entropy_leaves_the_bit_depth_alone() {
+        assert_eq!(statistics(0.0), (BIT_DEPTH, BIT_DEPTH, BIT_DEPTH));
+    }
+
+    #[test]
+    fn half_entropy_covers_the_upper_half() {
+        let (min, max, mean) = statistics(0.5);
+        assert!((9.0..9.1).contains(&min), "{min}");
+        assert!(max > 15.9 && max <= BIT_DEPTH, "{max}");
+        assert!((mean - 12.5).abs() < 0.1, "{mean}");
+    }
+
+    #[test]
+    fn full_entropy_covers_the_whole_range() {
+        let (min, max, mean) = statistics(1.0);
+        assert!((FLOOR..2.1).contains(&min), "{min}");
+        assert!(max > 15.9 && max <= BIT_DEPTH, "{max}");
+        assert!((mean - 9.0).abs() < 0.2, "{mean}");
+    }
+
+    #[test]
+    fn bit_depths_below_the_floor_are_left_alone() {
+        assert_eq!(perturb_bit_depth(1.5, 1.0, 4.0, 1.0), 1.5);
+        assert_eq!(perturb_bit_depth(8.0, 1.0, 0.0, 1.0), MIN_BIT_DEPTH);
+    }
+
+    /// Run a clock at `increment` for `num_samples` samples, syncing it to a transport that starts
+    /// at `start` intervals before every block of `block_size` samples. `error` gets added to every
+    /// other synced position to simulate rounding errors. Returns the samples where new values were
+    /// drawn.
+    fn synced_draws(start: f64, increment: f64, block_size: usize, error: f64) -> Vec<usize> {
+        let mut clock = RandomClock::default();
+        let mut draws = Vec::new();
+        for i in 0..256 {
+            if i % block_size == 0 {
+                let error = if (i / block_size) % 2 == 1 {
+                    error
+                } else {
+                    0.0
+                };
+                clock.set_position(start + (i as f64 * increment) + error);
+            }
+            clock.next(increment, 0.0, || {
+                draws.push(i);
+                0.0
+            });
+        }
+
+        draws
+    }
+
+    #[test]
+    fn synced_draws_land_on_the_grid() {
+        // Starting 3/8 into an interval, the first draw after the initial one should happen five
+        // samples in
+        let draws = synced_draws(2.375, 0.125, 7, 0.0);
+        assert_eq!(draws[..4], [0, 5, 13, 21]);
+        assert_eq!(draws.len(), 1 + (5..256).step_by(8).count());
+
+        // The grid doesn't depend on the block size, and tiny rounding errors don't cause extra
+        // draws
+        for block_size in [1, 3, 8, 64] {
+            for error in [-1e-9, 0.0, 1e-9] {
+                assert_eq!(synced_draws(2.375, 0.125, block_size, error), draws);
+            }
+        }
+    }
+
+    #[test]
+    fn synced_jumps_draw_a_new_value() {
+        let mut clock = RandomClock::default();
+        let mut value = 0.0;
+        let mut draw = || {
+            value += 1.0;
+            value
+        };
+        clock.set_position(4.5);
+        assert_eq!(clock.next(0.01, 0.0, &mut draw), 1.0);
+
+        // Moving within the same interval keeps the value
+        clock.set_position(4.8);
+        assert_eq!(clock.next(0.01, 0.0, &mut draw), 1.0);
+        // While jumping to another interval, even backwards, draws a new one
+        clock.set_position(1.2);
+        assert_eq!(clock.next(0.01, 0.0, &mut draw), 2.0);
+        assert_eq!(clock.next(0.01, 0.0, &mut draw), 2.0);
+    }
+}
@@ -0,0 +1,385 @@
+//! The random processes Entropy can draw its values from.
+
+use nih_plug::prelude::*;
+use rand::prelude::*;
+
+/// Something that produces a new value between `0.0` and `1.0` every time Entropy picks a new
+/// random value. Sources can keep state between draws, so they can be smooth or correlated.
+pub trait EntropySource {
+    /// Draw the next value, between `0.0` and `1.0`.
+    fn next(&mut self, rng: &mut StdRng) -> f32;
+
+    /// Forget any state kept between draws.
+    fn reset(&mut self);
+}
+
+/// Which [`EntropySource`] Entropy draws its values from.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntropySourceKind {
+    #[id = "uniform"]
+    #[name = "Uniform"]
+    Uniform,
+    #[id = "gaussian"]
+    #[name = "Gaussian"]
+    Gaussian,
+    #[id = "random_walk"]
+    #[name = "Random Walk"]
+    RandomWalk,
+    #[id = "smooth"]
+    #[name = "Smooth"]
+    Smooth,
+    #[id = "chaos"]
+    #[name = "Chaos"]
+    Chaos,
+    #[id = "pink"]
+    #[name = "Pink"]
+    Pink,
+}
+
+/// Every value is equally likely and independent of the previous one.
+#[derive(Debug, Default)]
+pub struct Uniform;
+
+impl EntropySource for Uniform {
+    fn next(&mut self, rng: &mut StdRng) -> f32 {
+        rng.gen()
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Normally distributed values centered on `0.5` with a standard deviation of `1/6`, so values
+/// near the middle are the most likely and the extremes are rare. Clamped to `[0, 1]`.
+#[derive(Debug, Default)]
+pub struct Gaussian;
+
+impl EntropySource for Gaussian {
+    fn next(&mut self, rng: &mut StdRng) -> f32 {
+        // Box-Muller, `1.0 - gen()` keeps the logarithm's argument away from zero
+        let u1: f32 = 1.0 - rng.gen::<f32>();
+        let u2: f32 = rng.gen();
+        let normal = (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+
+        (0.5 + (normal / 6.0)).clamp(0.0, 1.0)
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Brownian motion. Every draw takes a random step of up to [`RandomWalk::MAX_STEP`] from the
+/// previous value, bouncing off of the edges of the range.
+#[derive(Debug)]
+pub struct RandomWalk {
+    value: f32,
+}
+
+impl RandomWalk {
+    pub const MAX_STEP: f32 = 0.1;
+}
+
+impl Default for RandomWalk {
+    fn default() -> Self {
+        Self { value: 0.5 }
+    }
+}
+
+impl EntropySource for RandomWalk {
+    fn next(&mut self, rng: &mut StdRng) -> f32 {
+        let mut value = self.value + rng.gen_range(-Self::MAX_STEP..Self::MAX_STEP);
+        if value < 0.0 {
+            value = -value;
+        } else if value > 1.0 {
+            value = 2.0 - value;
+        }
+        self.value = value;
+
+        value
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// One dimensional Perlin style gradient noise. Every draw moves [`Smooth::STEP`] along the noise,
+/// so it takes a couple of draws to get from one random gradient to the next.
+#[derive(Debug, Default)]
+pub struct Smooth {
+    /// The position between the two lattice points, between `0.0` and `1.0`.
+    position: f32,
+    /// The gradients at the lattice points on either side of `position`, or `None` after a reset.
+    gradients: Option<(f32, f32)>,
+}
+
+impl Smooth {
+    pub const STEP: f32 = 0.25;
+}
+
+impl EntropySource for Smooth {
+    fn next(&mut self, rng: &mut StdRng) -> f32 {
+        let (mut g0, mut g1) = match self.gradients {
+            Some(gradients) => gradients,
+            None => (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+        };
+
+        self.position += Self::STEP;
+        if self.position >= 1.0 {
+            self.position -= 1.0;
+            g0 = g1;
+            g1 = rng.gen_range(-1.0..1.0);
+        }
+        self.gradients = Some((g0, g1));
+
+        let t = self.position;
+        let fade = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let noise = (g0 * t) + ((g1 * (t - 1.0) - g0 * t) * fade);
+
+        // The noise itself stays within `[-0.5, 0.5]`
+        (0.5 + noise).clamp(0.0, 1.0)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The logistic map `x = r * x * (1 - x)` in its chaotic regime. Fully deterministic after the
+/// first value, but never repeats and tends to linger near the edges of the range.
+#[derive(Debug, Default)]
+pub struct Chaos {
+    /// The current value, or `None` after a reset.
+    x: Option<f32>,
+}
+
+impl Chaos {
+    pub const R: f32 = 3.99;
+}
+
+impl EntropySource for Chaos {
+    fn next(&mut self, rng: &mut StdRng) -> f32 {
+        let x = self.x.unwrap_or_else(|| rng.gen_range(0.1..0.9));
+        let mut next = Self::R * x * (1.0 - x);
+        // Rounding errors can land the map on a fixed point, kick it off again if that happens
+        if !(0.0001..0.9999).contains(&next) || next == x {
+            next = rng.gen_range(0.1..0.9);
+        }
+        self.x = Some(next);
+
+        next
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Pink (`1/f`) noise using the Voss-McCartney algorithm. Every draw updates one of
+/// [`Pink::NUM_ROWS`] random values, with row `n` getting updated every `2^n` draws, and returns
+/// their average.
+#[derive(Debug, Default)]
+pub struct Pink {
+    rows: [f32; Pink::NUM_ROWS],
+    counter: u32,
+    /// Set after the first draw, since all rows start out with new random values.
+    initialized: bool,
+}
+
+impl Pink {
+    pub const NUM_ROWS: usize = 8;
+}
+
+impl EntropySource for Pink {
+    fn next(&mut self, rng: &mut StdRng) -> f32 {
+        if !self.initialized {
+            self.initialized = true;
+            for row in &mut self.rows {
+                *row = rng.gen();
+            }
+        }
+
+        let row = (self.counter.trailing_zeros() as usize).min(Self::NUM_ROWS - 1);
+        self.rows[row] = rng.gen();
+        self.counter = self.counter.wrapping_add(1);
+
+        // An extra white noise term fills in the top octave
+        let white: f32 = rng.gen();
+        (self.rows.iter().sum::<f32>() + white) / (Self::NUM_ROWS + 1) as f32
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// One instance of every source, so switching sources doesn't allocate.
+#[derive(Debug, Default)]
+pub struct EntropySources {
+    uniform: Uniform,
+    gaussian: Gaussian,
+    random_walk: RandomWalk,
+    smooth: Smooth,
+    chaos: Chaos,
+    pink: Pink,
+}
+
+impl EntropySources {
+    pub fn get_mut(&mut self, kind: EntropySourceKind) -> &mut dyn EntropySource {
+        match kind {
+            EntropySourceKind::Uniform => &mut self.uniform,
+            EntropySourceKind::Gaussian => &mut self.gaussian,
+            EntropySourceKind::RandomWalk => &mut self.random_walk,
+            EntropySourceKind::Smooth => &mut self.smooth,
+            EntropySourceKind::Chaos => &mut self.chaos,
+            EntropySourceKind::Pink => &mut self.pink,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.uniform.reset();
+        self.gaussian.reset();
+        self.random_walk.reset();
+        self.smooth.reset();
+        self.chaos.reset();
+        self.pink.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::test_util::band_energy;
+
+    const NUM_DRAWS: usize = 4096;
+    const KINDS: [EntropySourceKind; 6] = [
+        EntropySourceKind::Uniform,
+        EntropySourceKind::Gaussian,
+        EntropySourceKind::RandomWalk,
+        EntropySourceKind::Smooth,
+        EntropySourceKind::Chaos,
+        EntropySourceKind::Pink,
+    ];
+
+    fn draw(kind: EntropySourceKind, seed: u64) -> Vec<f32> {
+        let mut sources = EntropySources::default();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let source = sources.get_mut(kind);
+
+        (0..NUM_DRAWS).map(|_| source.next(&mut rng)).collect()
+    }
+
+    fn mean(values: &[f32]) -> f32 {
+        values.iter().sum::<f32>() / values.len() as f32
+    }
+
+    fn standard_deviation(values: &[f32]) -> f32 {
+        let mean = mean(values);
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>()
+            / values.len() as f32;
+
+        variance.sqrt()
+    }
+
+    /// The largest difference between two consecutive draws.
+    fn largest_step(values: &[f32]) -> f32 {
+        values
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    /// The ratio between the energy in the lowest and the highest eighth of the spectrum.
+    fn tilt(values: &[f32]) -> f64 {
+        let mean = mean(values);
+        let centered: Vec<f32> = values.iter().map(|value| value - mean).collect();
+
+        band_energy(&centered, 0.0, 0.0625) / band_energy(&centered, 0.4375, 0.5)
+    }
+
+    #[test]
+    fn every_source_stays_in_range() {
+        for kind in KINDS {
+            for seed in 0..4 {
+                let values = draw(kind, seed);
+                assert!(
+                    values.iter().all(|value| (0.0..=1.0).contains(value)),
+                    "{kind:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_sources_are_deterministic() {
+        for kind in KINDS {
+            assert_eq!(draw(kind, 3), draw(kind, 3), "{kind:?}");
+            assert_ne!(draw(kind, 3), draw(kind, 4), "{kind:?}");
+
+            // Resetting a source and reseeding the generator starts it over
+            let mut sources = EntropySources::default();
+            let mut rng = StdRng::seed_from_u64(3);
+            let source = sources.get_mut(kind);
+            for _ in 0..100 {
+                source.next(&mut rng);
+            }
+            sources.reset();
+            let mut rng = StdRng::seed_from_u64(3);
+            let source = sources.get_mut(kind);
+            let values: Vec<f32> = (0..NUM_DRAWS).map(|_| source.next(&mut rng)).collect();
+            assert_eq!(values, draw(kind, 3), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn uniform_is_flat() {
+        let values = draw(EntropySourceKind::Uniform, 1);
+        assert!((mean(&values) - 0.5).abs() < 0.02);
+        // `1 / sqrt(12)`
+        assert!((standard_deviation(&values) - 0.289).abs() < 0.01);
+        assert!((0.5..2.0).contains(&tilt(&values)));
+    }
+
+    #[test]
+    fn gaussian_is_centered() {
+        let values = draw(EntropySourceKind::Gaussian, 1);
+        assert!((mean(&values) - 0.5).abs() < 0.02);
+        assert!((standard_deviation(&values) - (1.0 / 6.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn random_walk_takes_small_steps() {
+        let values = draw(EntropySourceKind::RandomWalk, 1);
+        assert!(largest_step(&values) <= RandomWalk::MAX_STEP);
+    }
+
+    #[test]
+    fn smooth_is_continuous() {
+        // The gradient noise can't move more than about 0.3 in a quarter of a lattice step, while
+        // uniform noise regularly jumps across almost the whole range
+        let values = draw(EntropySourceKind::Smooth, 1);
+        assert!(largest_step(&values) < 0.35);
+        assert!(largest_step(&draw(EntropySourceKind::Uniform, 1)) > 0.9);
+    }
+
+    #[test]
+    fn chaos_never_sticks() {
+        let mut chaos = Chaos::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let values: Vec<f32> = (0..100_000).map(|_| chaos.next(&mut rng)).collect();
+
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+        for window in values.chunks(1000) {
+            let min = window.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = window.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            assert!(max - min > 0.9, "{min}..{max}");
+        }
+    }
+
+    #[test]
+    fn pink_tilts_toward_the_low_end() {
+        let values = draw(EntropySourceKind::Pink, 1);
+        assert!(tilt(&values) > 10.0, "{}", tilt(&values));
+    }
+}
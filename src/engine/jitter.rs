@@ -0,0 +1,36 @@
+use rand::Rng;
+
+/// The highest correlation [`Jitter::next()`] uses, so the low-pass filter never fully stops
+/// moving.
+const MAX_CORRELATION: f32 = 0.99;
+
+/// Emulates an unstable sampling clock by stretching and squashing every Redux hold interval by a
+/// random amount. With correlation the random values get low-pass filtered across hold intervals,
+/// which turns the jitter into a slower wow-like drift.
+#[derive(Default)]
+pub struct Jitter {
+    /// The low-passed random value, between `-1.0` and `1.0`.
+    state: f32,
+}
+
+impl Jitter {
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+    }
+
+    /// Get the multiplier for the length of the next hold interval. `amount` is the maximum
+    /// deviation in octaves, and `correlation` is between `0.0` for white jitter and `1.0` for
+    /// heavily low-passed jitter.
+    pub fn next(&mut self, amount: f32, correlation: f32, rng: &mut impl Rng) -> f32 {
+        let correlation = correlation.clamp(0.0, MAX_CORRELATION);
+        let white: f32 = rng.gen_range(-1.0..1.0);
+        self.state = (self.state * correlation) + (white * (1.0 - correlation));
+
+        // The one-pole filter reduces the variance by `(1 - c) / (1 + c)`, so this is scaled back
+        // up to keep the same depth regardless of the correlation
+        let normalization = ((1.0 + correlation) / (1.0 - correlation)).sqrt();
+        let deviation = (self.state * normalization).clamp(-1.0, 1.0);
+
+        (amount * deviation).exp2()
+    }
+}
@@ -0,0 +1,142 @@
+use nih_plug::prelude::*;
+
+/// The longest error feedback filter, in samples.
+const MAX_ORDER: usize = 9;
+
+/// The error feedback filter wrapped around the quantizer. The resulting noise transfer function
+/// is `1 - H(z)`, where `H(z)` is the filter defined by [`NoiseShaping::coefficients()`].
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseShaping {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    /// `1 - z^-1`, a gentle 6 dB/octave tilt that moves noise away from the low end.
+    #[id = "first_order"]
+    #[name = "First Order"]
+    FirstOrder,
+    /// Wannamaker's 9 tap F-weighted filter, which puts the noise where the ear is least
+    /// sensitive. Designed for 44.1 kHz. The noise shaper runs inside of the oversampled Crush
+    /// stage, so with oversampling enabled the curve gets stretched by the oversampling factor and
+    /// most of the shaped noise lands above the host's Nyquist frequency where it gets filtered
+    /// out. What's left in the audible range is mostly the curve's low end, not the F-weighting.
+    #[id = "f_weighted"]
+    #[name = "F-Weighted"]
+    FWeighted,
+    /// `(1 + z^-1)^2`, which does the opposite of what you'd normally want and piles the noise up
+    /// in the low and mid range. Only useful as an effect.
+    #[id = "inverse"]
+    #[name = "Inverse"]
+    Inverse,
+}
+
+impl NoiseShaping {
+    /// The error feedback coefficients, starting at the previous sample's error.
+    pub fn coefficients(self) -> &'static [f32] {
+        match self {
+            NoiseShaping::Off => &[],
+            NoiseShaping::FirstOrder => &[1.0],
+            NoiseShaping::FWeighted => &[
+                2.412, -3.370, 3.937, -4.174, 3.353, -2.205, 1.281, -0.569, 0.0847,
+            ],
+            NoiseShaping::Inverse => &[-2.0, -1.0],
+        }
+    }
+}
+
+/// Keeps track of the quantization error for every channel.
+pub struct NoiseShaper {
+    /// The last [`MAX_ORDER`] errors for every channel, with the most recent error first.
+    errors: Vec<[f32; MAX_ORDER]>,
+}
+
+impl NoiseShaper {
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            errors: vec![[0.0; MAX_ORDER]; num_channels],
+        }
+    }
+
+    /// Resize the per-channel state. This allocates.
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        self.errors.resize(num_channels, [0.0; MAX_ORDER]);
+    }
+
+    pub fn reset(&mut self) {
+        for errors in &mut self.errors {
+            errors.fill(0.0);
+        }
+    }
+
+    /// The filtered error that should be subtracted from the quantizer's input.
+    pub fn feedback(&self, channel: usize, mode: NoiseShaping) -> f32 {
+        mode.coefficients()
+            .iter()
+            .zip(self.errors[channel].iter())
+            .map(|(coefficient, error)| coefficient * error)
+            .sum()
+    }
+
+    /// Store the difference between the quantizer's output and its (shaped) input.
+    pub fn push_error(&mut self, channel: usize, error: f32) {
+        let errors = &mut self.errors[channel];
+        errors.copy_within(..MAX_ORDER - 1, 1);
+        errors[0] = error;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::quantizer::{quantize, QuantizerMode};
+    use crate::engine::test_util::band_energy;
+    use rand::prelude::*;
+
+    /// The total quantization error after running white noise through a shaped 8-bit quantizer.
+    fn shaped_error(mode: NoiseShaping) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut noise_shaper = NoiseShaper::new(1);
+        let step = 1.0 / 256.0;
+
+        (0..2048)
+            .map(|_| {
+                let sample: f32 = rng.gen_range(-0.5..0.5);
+                let shaped = sample - noise_shaper.feedback(0, mode);
+                let quantized = quantize(shaped, step, QuantizerMode::Round);
+                noise_shaper.push_error(0, quantized - shaped);
+
+                quantized - sample
+            })
+            .collect()
+    }
+
+    /// The error energy in the top eighth of the spectrum divided by that in the bottom eighth.
+    fn tilt(mode: NoiseShaping) -> f64 {
+        let error = shaped_error(mode);
+        band_energy(&error, 0.375, 0.5) / band_energy(&error, 0.0, 0.125)
+    }
+
+    #[test]
+    fn unshaped_error_is_flat() {
+        let tilt = tilt(NoiseShaping::Off);
+        assert!((0.5..2.0).contains(&tilt), "{tilt}");
+    }
+
+    #[test]
+    fn first_order_moves_noise_up() {
+        // `|1 - e^-jw|^2` averages to about 3.8 in the top band and 0.2 in the bottom band
+        let tilt = tilt(NoiseShaping::FirstOrder);
+        assert!(tilt > 10.0, "{tilt}");
+    }
+
+    #[test]
+    fn f_weighted_moves_noise_up() {
+        let tilt = tilt(NoiseShaping::FWeighted);
+        assert!(tilt > 20.0, "{tilt}");
+    }
+
+    #[test]
+    fn inverse_moves_noise_down() {
+        let tilt = tilt(NoiseShaping::Inverse);
+        assert!(tilt < 0.05, "{tilt}");
+    }
+}
@@ -0,0 +1,242 @@
+use nih_plug::prelude::*;
+use std::f32::consts::PI;
+
+/// The highest oversampling factor.
+pub const MAX_OVERSAMPLING: usize = 8;
+/// The number of 2x stages needed for [`MAX_OVERSAMPLING`].
+const MAX_STAGES: usize = 3;
+/// The number of non-zero taps on either side of the half-band filter's center tap. The filter
+/// has `4 * HALF_TAPS - 1` taps in total.
+const HALF_TAPS: usize = 16;
+/// The length of the filter, and thus the delay line for the decimators.
+const FILTER_LENGTH: usize = (4 * HALF_TAPS) - 1;
+/// The length of the delay line for the interpolators. This has room for one extra sample, see
+/// [`Oversampler::upsample()`].
+const UPSAMPLER_LENGTH: usize = (2 * HALF_TAPS) + 1;
+
+/// How much the Crush stage gets oversampled.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oversampling {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    #[id = "2x"]
+    #[name = "2x"]
+    X2,
+    #[id = "4x"]
+    #[name = "4x"]
+    X4,
+    #[id = "8x"]
+    #[name = "8x"]
+    X8,
+}
+
+impl Oversampling {
+    /// The number of cascaded 2x stages.
+    fn num_stages(self) -> usize {
+        match self {
+            Oversampling::Off => 0,
+            Oversampling::X2 => 1,
+            Oversampling::X4 => 2,
+            Oversampling::X8 => 3,
+        }
+    }
+
+    pub fn factor(self) -> usize {
+        1 << self.num_stages()
+    }
+
+    /// The latency introduced by the up and downsampling filters, in samples at the host's sample
+    /// rate. The outer stage delays the signal by `2 * HALF_TAPS - 1` samples. The inner stages
+    /// are padded to `2 * HALF_TAPS` samples at their own rate so the total stays a whole number
+    /// of samples.
+    pub fn latency_samples(self) -> u32 {
+        (0..self.num_stages())
+            .map(|stage| match stage {
+                0 => (2 * HALF_TAPS) - 1,
+                _ => (2 * HALF_TAPS) >> stage,
+            })
+            .sum::<usize>() as u32
+    }
+}
+
+/// Cascaded polyphase half-band filters for up and downsampling a single sample at a time. Every
+/// stage only has to compute the half of the filter's taps that aren't zero.
+pub struct Oversampler {
+    /// The non-zero taps on one side of the half-band filter, starting next to the center tap. The
+    /// center tap is always `0.5`.
+    coefficients: [f32; HALF_TAPS],
+    /// The last [`UPSAMPLER_LENGTH`] input samples for every channel and interpolator stage, with
+    /// the most recent sample first.
+    upsampler_states: Vec<[[f32; UPSAMPLER_LENGTH]; MAX_STAGES]>,
+    /// The last [`FILTER_LENGTH`] input samples for every channel and decimator stage, with the
+    /// most recent sample first.
+    downsampler_states: Vec<[[f32; FILTER_LENGTH]; MAX_STAGES]>,
+}
+
+impl Oversampler {
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            coefficients: half_band_coefficients(),
+            upsampler_states: vec![[[0.0; UPSAMPLER_LENGTH]; MAX_STAGES]; num_channels],
+            downsampler_states: vec![[[0.0; FILTER_LENGTH]; MAX_STAGES]; num_channels],
+        }
+    }
+
+    /// Resize the per-channel state. This allocates.
+    pub fn set_num_channels(&mut self, num_channels: usize) {
+        self.upsampler_states
+            .resize(num_channels, [[0.0; UPSAMPLER_LENGTH]; MAX_STAGES]);
+        self.downsampler_states
+            .resize(num_channels, [[0.0; FILTER_LENGTH]; MAX_STAGES]);
+    }
+
+    pub fn reset(&mut self) {
+        for states in &mut self.upsampler_states {
+            *states = [[0.0; UPSAMPLER_LENGTH]; MAX_STAGES];
+        }
+        for states in &mut self.downsampler_states {
+            *states = [[0.0; FILTER_LENGTH]; MAX_STAGES];
+        }
+    }
+
+    /// Upsample a single sample for `channel`. The first [`Oversampling::factor()`] samples of
+    /// `output` are filled with the upsampled signal.
+    pub fn upsample(
+        &mut self,
+        channel: usize,
+        oversampling: Oversampling,
+        sample: f32,
+        output: &mut [f32; MAX_OVERSAMPLING],
+    ) {
+        let mut scratch = [0.0; MAX_OVERSAMPLING];
+        output[0] = sample;
+        for (stage, state) in self.upsampler_states[channel][..oversampling.num_stages()]
+            .iter_mut()
+            .enumerate()
+        {
+            let num_samples = 1 << stage;
+            // The inner stages get delayed by one more sample to keep the total latency whole
+            let delay = HALF_TAPS + usize::from(stage > 0);
+            for (i, &sample) in output[..num_samples].iter().enumerate() {
+                state.copy_within(..UPSAMPLER_LENGTH - 1, 1);
+                state[0] = sample;
+
+                // With a half-band filter one of the two phases is just a delayed copy of the
+                // input, the other one only needs the odd taps. These are scaled by two to make up
+                // for the zeroes that got stuffed in between the samples.
+                let mut interpolated = 0.0;
+                for (j, coefficient) in self.coefficients.iter().enumerate() {
+                    interpolated += coefficient * (state[delay - 1 - j] + state[delay + j]);
+                }
+                scratch[2 * i] = state[delay];
+                scratch[(2 * i) + 1] = 2.0 * interpolated;
+            }
+
+            output[..num_samples * 2].copy_from_slice(&scratch[..num_samples * 2]);
+        }
+    }
+
+    /// Downsample the first [`Oversampling::factor()`] samples of `input` back to a single sample
+    /// for `channel`. `input` gets overwritten in the process.
+    pub fn downsample(
+        &mut self,
+        channel: usize,
+        oversampling: Oversampling,
+        input: &mut [f32; MAX_OVERSAMPLING],
+    ) -> f32 {
+        for (stage, state) in self.downsampler_states[channel][..oversampling.num_stages()]
+            .iter_mut()
+            .enumerate()
+            .rev()
+        {
+            let num_samples = 1 << stage;
+            for i in 0..num_samples {
+                state.copy_within(..FILTER_LENGTH - 2, 2);
+                state[1] = input[2 * i];
+                state[0] = input[(2 * i) + 1];
+
+                let center = (FILTER_LENGTH - 1) / 2;
+                let mut decimated = 0.5 * state[center];
+                for (j, coefficient) in self.coefficients.iter().enumerate() {
+                    let offset = (2 * j) + 1;
+                    decimated += coefficient * (state[center - offset] + state[center + offset]);
+                }
+                input[i] = decimated;
+            }
+        }
+
+        input[0]
+    }
+}
+
+/// A Blackman windowed sinc half-band low-pass filter. Only the taps at odd distances from the
+/// center tap are returned, since all other taps except for the center are zero.
+fn half_band_coefficients() -> [f32; HALF_TAPS] {
+    let mut coefficients = [0.0; HALF_TAPS];
+    for (j, coefficient) in coefficients.iter_mut().enumerate() {
+        let distance = ((2 * j) + 1) as f32;
+        let sinc = (PI * distance / 2.0).sin() / (PI * distance);
+        let n = (((FILTER_LENGTH / 2) as f32 + distance) + 1.0) / (FILTER_LENGTH + 1) as f32;
+        let window = 0.42 - (0.5 * (2.0 * PI * n).cos()) + (0.08 * (4.0 * PI * n).cos());
+        *coefficient = sinc * window;
+    }
+
+    // The taps on both sides plus the center tap should sum to one for unity gain at DC
+    let sum: f32 = coefficients.iter().sum();
+    for coefficient in &mut coefficients {
+        *coefficient *= 0.25 / sum;
+    }
+
+    coefficients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_matches_the_impulse_response() {
+        for (oversampling, latency) in [
+            (Oversampling::X2, 31),
+            (Oversampling::X4, 47),
+            (Oversampling::X8, 55),
+        ] {
+            assert_eq!(oversampling.latency_samples(), latency);
+
+            let mut oversampler = Oversampler::new(1);
+            let response: Vec<f32> = (0..128)
+                .map(|i| {
+                    let mut oversampled = [0.0; MAX_OVERSAMPLING];
+                    let impulse = if i == 0 { 1.0 } else { 0.0 };
+                    oversampler.upsample(0, oversampling, impulse, &mut oversampled);
+                    oversampler.downsample(0, oversampling, &mut oversampled)
+                })
+                .collect();
+
+            // The half-band filters are linear phase, so the impulse comes out at the center of
+            // the response and the response is symmetric around it
+            let peak = response
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+                .unwrap()
+                .0;
+            assert_eq!(peak, latency as usize, "{oversampling:?}");
+            for offset in 1..=latency as usize {
+                let (before, after) = (response[peak - offset], response[peak + offset]);
+                assert!((before - after).abs() < 1e-6, "{oversampling:?}");
+            }
+            assert!(
+                (response.iter().sum::<f32>() - 1.0).abs() < 1e-4,
+                "{oversampling:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn off_has_no_latency() {
+        assert_eq!(Oversampling::Off.latency_samples(), 0);
+        assert_eq!(Oversampling::Off.factor(), 1);
+    }
+}
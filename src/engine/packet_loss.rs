@@ -0,0 +1,312 @@
+use nih_plug::prelude::*;
+use rand::Rng;
+
+/// The longest packet the packet loss stage can simulate, in milliseconds. This determines how
+/// much memory gets allocated for [`Concealment::Repeat`] and [`Concealment::Fade`].
+pub const MAX_PACKET_LENGTH_MS: f32 = 100.0;
+
+/// What gets played in place of a lost packet.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concealment {
+    /// Lost packets are muted.
+    #[id = "silence"]
+    #[name = "Silence"]
+    Silence,
+    /// Lost packets are replaced with the last packet that did arrive.
+    #[id = "repeat"]
+    #[name = "Repeat"]
+    Repeat,
+    /// The last packet that did arrive is repeated once while fading out, after which any
+    /// consecutive lost packets are muted.
+    #[id = "fade"]
+    #[name = "Fade"]
+    Fade,
+    /// Lost packets are replaced with white noise at the level of the last packet that did
+    /// arrive.
+    #[id = "noise"]
+    #[name = "Noise"]
+    Noise,
+}
+
+/// Simulates audio sent over a lossy network. The signal is cut up into fixed length packets, and
+/// every packet has a chance of getting lost, in which case it's replaced according to the
+/// [`Concealment`] mode. Packets are shared between all channels.
+pub struct PacketLoss {
+    /// The last packet that arrived, for every channel.
+    last_packet: Vec<Vec<f32>>,
+    /// The packet that's currently arriving, for every channel. This gets swapped with
+    /// `last_packet` at the end of the packet if the packet wasn't lost.
+    current_packet: Vec<Vec<f32>>,
+    /// The RMS level of `last_packet`, for every channel.
+    last_rms: Vec<f32>,
+    /// The sum of the squares of the samples in `current_packet`, for every channel.
+    sum_squares: Vec<f32>,
+
+    /// The length of `last_packet` in samples, zero if no packet has arrived yet.
+    last_length: usize,
+    /// The length of the current packet in samples. This is latched at the start of a packet.
+    length: usize,
+    /// The position within the current packet, in samples.
+    position: usize,
+    /// Whether the current packet is lost.
+    lost: bool,
+    /// Whether the current packet is the first lost packet after one that arrived, used for
+    /// [`Concealment::Fade`].
+    first_lost: bool,
+}
+
+impl PacketLoss {
+    pub fn new(num_channels: usize, max_packet_length: usize) -> Self {
+        Self {
+            last_packet: vec![vec![0.0; max_packet_length]; num_channels],
+            current_packet: vec![vec![0.0; max_packet_length]; num_channels],
+            last_rms: vec![0.0; num_channels],
+            sum_squares: vec![0.0; num_channels],
+
+            last_length: 0,
+            length: 0,
+            position: 0,
+            lost: false,
+            first_lost: false,
+        }
+    }
+
+    /// Resize the per-channel buffers. This allocates.
+    pub fn resize(&mut self, num_channels: usize, max_packet_length: usize) {
+        *self = Self::new(num_channels, max_packet_length);
+    }
+
+    pub fn reset(&mut self) {
+        for packet in self
+            .last_packet
+            .iter_mut()
+            .chain(self.current_packet.iter_mut())
+        {
+            packet.fill(0.0);
+        }
+        self.last_rms.fill(0.0);
+        self.sum_squares.fill(0.0);
+        self.last_length = 0;
+        self.length = 0;
+        self.position = 0;
+        self.lost = false;
+        self.first_lost = false;
+    }
+
+    /// Called once at the start of every sample, before [`process()`][Self::process()] gets
+    /// called for the individual channels. Starts a new packet when the last one has ended.
+    /// `packet_length` is the packet length in samples and `probability` is the chance of that
+    /// new packet getting lost.
+    pub fn start_sample(&mut self, packet_length: usize, probability: f32, rng: &mut impl Rng) {
+        if self.position < self.length {
+            return;
+        }
+
+        // The packet that just ended becomes the one used for concealment, as long as it arrived
+        if self.length > 0 && !self.lost {
+            std::mem::swap(&mut self.last_packet, &mut self.current_packet);
+            for (rms, sum_squares) in self.last_rms.iter_mut().zip(self.sum_squares.iter_mut()) {
+                *rms = (*sum_squares / self.length as f32).sqrt();
+                *sum_squares = 0.0;
+            }
+            self.last_length = self.length;
+        }
+
+        let max_packet_length = self.current_packet.first().map_or(0, Vec::len);
+        let was_lost = self.lost;
+        self.length = packet_length.clamp(1, max_packet_length.max(1));
+        self.position = 0;
+        self.lost = probability > 0.0 && rng.gen::<f32>() < probability;
+        self.first_lost = self.lost && !was_lost;
+    }
+
+    /// Process a single sample for `channel`.
+    pub fn process(
+        &mut self,
+        channel: usize,
+        sample: f32,
+        concealment: Concealment,
+        rng: &mut impl Rng,
+    ) -> f32 {
+        if !self.lost {
+            if let Some(recorded) = self.current_packet[channel].get_mut(self.position) {
+                *recorded = sample;
+                self.sum_squares[channel] += sample * sample;
+            }
+
+            return sample;
+        }
+
+        let repeated = if self.last_length > 0 {
+            self.last_packet[channel][self.position % self.last_length]
+        } else {
+            0.0
+        };
+        match concealment {
+            Concealment::Silence => 0.0,
+            Concealment::Repeat => repeated,
+            Concealment::Fade if self.first_lost => {
+                repeated * (1.0 - (self.position as f32 / self.length as f32))
+            }
+            Concealment::Fade => 0.0,
+            // Uniform noise between -1 and 1 has an RMS level of `1 / sqrt(3)`
+            Concealment::Noise => rng.gen_range(-1.0..1.0) * self.last_rms[channel] * 3.0f32.sqrt(),
+        }
+    }
+
+    /// Called once at the end of every sample, after all channels have been processed.
+    pub fn end_sample(&mut self) {
+        self.position += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    const PACKET_LENGTH: usize = 8;
+
+    /// A ramp that's different for every sample in the first couple of packets.
+    fn ramp(num_samples: usize) -> Vec<f32> {
+        (0..num_samples).map(|i| (i + 1) as f32 * 0.01).collect()
+    }
+
+    /// Run `input` through `packet_loss` with a single channel. `schedule` returns the packet
+    /// length and loss probability for every sample.
+    fn run(
+        packet_loss: &mut PacketLoss,
+        input: &[f32],
+        concealment: Concealment,
+        schedule: impl Fn(usize) -> (usize, f32),
+    ) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(18);
+        input
+            .iter()
+            .enumerate()
+            .map(|(i, &sample)| {
+                let (packet_length, probability) = schedule(i);
+                packet_loss.start_sample(packet_length, probability, &mut rng);
+                let output = packet_loss.process(0, sample, concealment, &mut rng);
+                packet_loss.end_sample();
+                output
+            })
+            .collect()
+    }
+
+    /// Let the first packet arrive and lose every packet after that.
+    fn lose_after_first(input: &[f32], concealment: Concealment) -> Vec<f32> {
+        let mut packet_loss = PacketLoss::new(1, PACKET_LENGTH);
+        run(&mut packet_loss, input, concealment, |i| {
+            (PACKET_LENGTH, if i < PACKET_LENGTH { 0.0 } else { 1.0 })
+        })
+    }
+
+    #[test]
+    fn arrived_packets_pass_through() {
+        let input = ramp(PACKET_LENGTH * 4);
+        let mut packet_loss = PacketLoss::new(1, PACKET_LENGTH);
+        let output = run(&mut packet_loss, &input, Concealment::Silence, |_| {
+            (PACKET_LENGTH, 0.0)
+        });
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn silence_mutes_lost_packets() {
+        let input = ramp(PACKET_LENGTH * 3);
+        let output = lose_after_first(&input, Concealment::Silence);
+
+        assert_eq!(output[..PACKET_LENGTH], input[..PACKET_LENGTH]);
+        assert!(output[PACKET_LENGTH..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn repeat_replays_the_last_packet() {
+        let input = ramp(PACKET_LENGTH * 3);
+        let output = lose_after_first(&input, Concealment::Repeat);
+
+        assert_eq!(
+            output[PACKET_LENGTH..PACKET_LENGTH * 2],
+            input[..PACKET_LENGTH]
+        );
+        assert_eq!(output[PACKET_LENGTH * 2..], input[..PACKET_LENGTH]);
+    }
+
+    #[test]
+    fn fade_repeats_once_while_fading_out() {
+        let input = ramp(PACKET_LENGTH * 3);
+        let output = lose_after_first(&input, Concealment::Fade);
+
+        for (i, sample) in output[PACKET_LENGTH..PACKET_LENGTH * 2].iter().enumerate() {
+            let fade = 1.0 - (i as f32 / PACKET_LENGTH as f32);
+            assert_eq!(*sample, input[i] * fade);
+        }
+        assert!(output[PACKET_LENGTH * 2..]
+            .iter()
+            .all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn noise_matches_the_last_packets_level() {
+        let input: Vec<f32> = (0..PACKET_LENGTH * 1000)
+            .map(|i| if i % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        let output = lose_after_first(&input, Concealment::Noise);
+
+        let noise = &output[PACKET_LENGTH..];
+        let rms =
+            (noise.iter().map(|sample| sample * sample).sum::<f32>() / noise.len() as f32).sqrt();
+        assert!((rms - 0.5).abs() < 0.02, "{rms}");
+        // Uniform noise at that level peaks at `0.5 * sqrt(3)`
+        assert!(noise
+            .iter()
+            .all(|sample| sample.abs() <= 0.5 * 3.0f32.sqrt()));
+        assert_ne!(noise[0], noise[1]);
+    }
+
+    #[test]
+    fn packet_length_is_latched_at_the_start_of_a_packet() {
+        // The length drops to 3 halfway through the first packet, which should still arrive in
+        // full. The lost packets after it are 3 samples long and all start from the beginning of
+        // that first packet.
+        let input = ramp(PACKET_LENGTH * 2);
+        let mut packet_loss = PacketLoss::new(1, PACKET_LENGTH);
+        let output = run(&mut packet_loss, &input, Concealment::Repeat, |i| {
+            let packet_length = if i < PACKET_LENGTH / 2 {
+                PACKET_LENGTH
+            } else {
+                3
+            };
+            (packet_length, if i < PACKET_LENGTH { 0.0 } else { 1.0 })
+        });
+
+        assert_eq!(output[..PACKET_LENGTH], input[..PACKET_LENGTH]);
+        for (i, sample) in output[PACKET_LENGTH..].iter().enumerate() {
+            assert_eq!(*sample, input[i % 3], "{i}");
+        }
+    }
+
+    #[test]
+    fn reset_forgets_the_last_packet() {
+        let loud = vec![0.9; PACKET_LENGTH * 3];
+        let quiet = vec![0.0; PACKET_LENGTH * 2];
+        for concealment in [Concealment::Repeat, Concealment::Fade, Concealment::Noise] {
+            let mut packet_loss = PacketLoss::new(1, PACKET_LENGTH);
+            run(&mut packet_loss, &loud, concealment, |_| {
+                (PACKET_LENGTH, 0.0)
+            });
+            packet_loss.reset();
+
+            // Losing the very first packet after a reset has nothing to conceal it with
+            let output = run(&mut packet_loss, &quiet, concealment, |_| {
+                (PACKET_LENGTH, 1.0)
+            });
+            assert!(
+                output.iter().all(|sample| *sample == 0.0),
+                "{concealment:?}"
+            );
+        }
+    }
+}
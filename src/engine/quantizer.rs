@@ -0,0 +1,238 @@
+use nih_plug::prelude::*;
+
+/// The kind of quantizer used for the Crush stage.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrushMode {
+    /// Evenly spaced quantization levels.
+    #[id = "linear"]
+    #[name = "Linear"]
+    Linear,
+    /// Compand with the mu-law curve before quantizing, like North American telephone lines.
+    #[id = "mu_law"]
+    #[name = "Mu-Law"]
+    MuLaw,
+    /// Compand with the A-law curve before quantizing, like European telephone lines.
+    #[id = "a_law"]
+    #[name = "A-Law"]
+    ALaw,
+    /// Keep the floating point exponent but throw away mantissa bits, like storing the signal as
+    /// fp16, bfloat16 or fp8. This ignores the bit depth and uses [`reduce_float()`] instead.
+    #[id = "float"]
+    #[name = "Float"]
+    Float,
+}
+
+/// How a sample gets snapped to the quantization grid. These all use the same step size, they
+/// only differ in which grid point a sample ends up on.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantizerMode {
+    /// Round toward zero. This leaves a dead zone around zero that is twice as wide as the other
+    /// steps, which makes for the grittiest sound.
+    #[id = "truncate"]
+    #[name = "Truncate"]
+    Truncate,
+    /// Round to the nearest step, with ties going to the even step so the error is unbiased.
+    #[id = "round"]
+    #[name = "Round"]
+    Round,
+    /// Round toward negative infinity. This adds a DC offset of half a step.
+    #[id = "floor"]
+    #[name = "Floor"]
+    Floor,
+    /// The textbook mid-tread quantizer, `floor(x / step + 0.5) * step`. There's an output level at
+    /// zero, so silence stays silent.
+    #[id = "mid_tread"]
+    #[name = "Mid-Tread"]
+    MidTread,
+    /// The textbook mid-rise quantizer, `(floor(x / step) + 0.5) * step`. There's a decision
+    /// threshold at zero, so low level signals turn into a square wave of half a step.
+    #[id = "mid_rise"]
+    #[name = "Mid-Rise"]
+    MidRise,
+}
+
+/// Quantize `sample` to a multiple of `step` (or an odd multiple of half a `step` for
+/// [`QuantizerMode::MidRise`]).
+pub fn quantize(sample: f32, step: f32, mode: QuantizerMode) -> f32 {
+    let scaled = sample / step;
+    let quantized = match mode {
+        QuantizerMode::Truncate => scaled.trunc(),
+        QuantizerMode::Round => scaled.round_ties_even(),
+        QuantizerMode::Floor => scaled.floor(),
+        QuantizerMode::MidTread => (scaled + 0.5).floor(),
+        QuantizerMode::MidRise => scaled.floor() + 0.5,
+    };
+
+    quantized * step
+}
+
+/// The number of explicitly stored mantissa bits in an `f32`.
+const F32_MANTISSA_BITS: u32 = 23;
+/// The number of exponent bits in an `f32`.
+const F32_EXPONENT_BITS: u32 = 8;
+
+/// Simulate storing `sample` in a smaller floating point format with `mantissa_bits` explicit
+/// mantissa bits and `exponent_bits` exponent bits. The mantissa is truncated toward zero, values
+/// too large for the format saturate at the largest finite value, and values too small to be
+/// normal are rounded to the format's subnormal grid. So fp16 is `(10, 5)`, bfloat16 is `(7, 8)`,
+/// and fp8 E4M3 and E5M2 are `(3, 4)` and `(2, 5)`.
+pub fn reduce_float(sample: f32, mantissa_bits: u32, exponent_bits: u32) -> f32 {
+    let mantissa_bits = mantissa_bits.min(F32_MANTISSA_BITS);
+    let dropped_bits = F32_MANTISSA_BITS - mantissa_bits;
+    let truncated = f32::from_bits(sample.to_bits() & (u32::MAX << dropped_bits));
+    if exponent_bits >= F32_EXPONENT_BITS || !truncated.is_finite() {
+        return truncated;
+    }
+
+    // This uses the IEEE 754 layout, so an all ones exponent is reserved for infinities and NaNs
+    let exponent_bits = exponent_bits.max(2) as i32;
+    let bias = (1 << (exponent_bits - 1)) - 1;
+    let min_normal = ((1 - bias) as f32).exp2();
+    let max_finite = (2.0 - (-(mantissa_bits as f32)).exp2()) * (bias as f32).exp2();
+
+    let abs = truncated.abs();
+    if abs > max_finite {
+        max_finite.copysign(truncated)
+    } else if abs < min_normal {
+        let subnormal_step = (1 - bias - mantissa_bits as i32) as f32;
+        quantize(truncated, subnormal_step.exp2(), QuantizerMode::Truncate)
+    } else {
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs on a grid with a step of a quarter, covering ties, both sides of zero and the zero
+    /// crossing itself. All of these and the expected values are exact in binary.
+    const INPUTS: [f32; 9] = [-0.375, -0.3, -0.125, -0.1, 0.0, 0.1, 0.125, 0.3, 0.375];
+    const STEP: f32 = 0.25;
+
+    fn staircase(mode: QuantizerMode) -> Vec<f32> {
+        INPUTS
+            .iter()
+            .map(|&sample| quantize(sample, STEP, mode))
+            .collect()
+    }
+
+    #[test]
+    fn truncate_rounds_toward_zero() {
+        assert_eq!(
+            staircase(QuantizerMode::Truncate),
+            [-0.25, -0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.25, 0.25]
+        );
+    }
+
+    #[test]
+    fn round_goes_to_the_nearest_even_step_on_ties() {
+        assert_eq!(
+            staircase(QuantizerMode::Round),
+            [-0.5, -0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.25, 0.5]
+        );
+    }
+
+    #[test]
+    fn floor_rounds_toward_negative_infinity() {
+        assert_eq!(
+            staircase(QuantizerMode::Floor),
+            [-0.5, -0.5, -0.25, -0.25, 0.0, 0.0, 0.0, 0.25, 0.25]
+        );
+    }
+
+    #[test]
+    fn mid_tread_rounds_ties_up() {
+        assert_eq!(
+            staircase(QuantizerMode::MidTread),
+            [-0.25, -0.25, 0.0, 0.0, 0.0, 0.0, 0.25, 0.25, 0.5]
+        );
+    }
+
+    #[test]
+    fn mid_rise_never_outputs_zero() {
+        assert_eq!(
+            staircase(QuantizerMode::MidRise),
+            [-0.375, -0.375, -0.125, -0.125, 0.125, 0.125, 0.125, 0.375, 0.375]
+        );
+    }
+
+    #[test]
+    fn round_and_mid_tread_only_differ_at_ties() {
+        for i in -1000..=1000 {
+            let sample = i as f32 / 1000.0;
+            let round = quantize(sample, STEP, QuantizerMode::Round);
+            let mid_tread = quantize(sample, STEP, QuantizerMode::MidTread);
+            // Mid-tread always rounds ties up, so they only disagree when the step below is even
+            let scaled = sample / STEP;
+            let is_tie = scaled.fract().abs() == 0.5;
+            let below_is_even = scaled.floor().rem_euclid(2.0) == 0.0;
+            assert_eq!(round != mid_tread, is_tie && below_is_even, "{sample}");
+        }
+    }
+
+    #[test]
+    fn fp16_truncates_the_mantissa() {
+        // 0.1 is 0x3DCCCCCD, fp16 keeps the top 10 of the 23 mantissa bits
+        assert_eq!(reduce_float(0.1, 10, 5).to_bits(), 0x3DCC_C000);
+        assert_eq!(reduce_float(-0.1, 10, 5).to_bits(), 0xBDCC_C000);
+        assert_eq!(reduce_float(1.0, 10, 5).to_bits(), 1.0f32.to_bits());
+    }
+
+    #[test]
+    fn fp16_saturates_at_max_finite() {
+        assert_eq!(reduce_float(70_000.0, 10, 5).to_bits(), 0x477F_E000);
+        assert_eq!(reduce_float(-1.0e6, 10, 5).to_bits(), 0xC77F_E000);
+        assert_eq!(reduce_float(65_504.0, 10, 5).to_bits(), 0x477F_E000);
+    }
+
+    #[test]
+    fn fp16_subnormals_use_a_fixed_grid() {
+        // The subnormal step is 2^-24, values in between get truncated toward zero
+        assert_eq!(
+            reduce_float(3.7 * 2.0f32.powi(-24), 10, 5).to_bits(),
+            0x3440_0000
+        );
+        assert_eq!(
+            reduce_float(-3.7 * 2.0f32.powi(-24), 10, 5).to_bits(),
+            0xB440_0000
+        );
+        assert_eq!(reduce_float(0.9 * 2.0f32.powi(-24), 10, 5), 0.0);
+    }
+
+    #[test]
+    fn bfloat16_keeps_the_f32_exponent_range() {
+        assert_eq!(reduce_float(0.1, 7, 8).to_bits(), 0x3DCC_0000);
+        // With eight exponent bits nothing saturates and subnormals are only truncated
+        assert_eq!(reduce_float(3.0e38, 7, 8).to_bits(), 0x7F61_0000);
+        assert_eq!(
+            reduce_float(f32::from_bits(0x0000_FFFF), 7, 8).to_bits(),
+            0x0000_0000
+        );
+        assert_eq!(
+            reduce_float(f32::from_bits(0x0012_3456), 7, 8).to_bits(),
+            0x0012_0000
+        );
+    }
+
+    #[test]
+    fn fp8_e4m3() {
+        // This uses the IEEE 754 layout, so the largest finite value is 240 rather than OCP's 448
+        assert_eq!(reduce_float(0.1, 3, 4).to_bits(), 0x3DC0_0000);
+        assert_eq!(reduce_float(1000.0, 3, 4).to_bits(), 0x4370_0000);
+        // 2^-6 is the smallest normal value and the subnormal step is 2^-9
+        assert_eq!(reduce_float(0.01, 3, 4).to_bits(), 0x3C20_0000);
+        assert_eq!(reduce_float(0.001, 3, 4), 0.0);
+    }
+
+    #[test]
+    fn fp8_e5m2() {
+        assert_eq!(reduce_float(0.1, 2, 5).to_bits(), 0x3DC0_0000);
+        assert_eq!(reduce_float(60_000.0, 2, 5).to_bits(), 0x4760_0000);
+        // 2^-14 is the smallest normal value and the subnormal step is 2^-16
+        assert_eq!(
+            reduce_float(3.5 * 2.0f32.powi(-16), 2, 5).to_bits(),
+            0x3840_0000
+        );
+    }
+}
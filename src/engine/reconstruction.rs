@@ -0,0 +1,130 @@
+use nih_plug::prelude::*;
+
+/// How the held samples from the Redux stage are turned back into a continuous signal, emulating
+/// the output stages of different sampler hardware. Everything except [`Reconstruction::Dac`]
+/// interpolates between past held samples, so those modes delay the reduced signal by one or two
+/// hold intervals.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconstruction {
+    /// The raw staircase.
+    #[id = "zoh"]
+    #[name = "Zero-Order Hold"]
+    ZeroOrderHold,
+    /// Straight lines between held samples, one hold interval late.
+    #[id = "linear"]
+    #[name = "Linear"]
+    Linear,
+    /// A Catmull-Rom spline through the held samples, two hold intervals late.
+    #[id = "cubic"]
+    #[name = "Cubic"]
+    Cubic,
+    /// The staircase followed by a steep low-pass filter at the reduced Nyquist frequency, like
+    /// the reconstruction filter after a DAC.
+    #[id = "dac"]
+    #[name = "DAC Filter"]
+    Dac,
+}
+
+impl Reconstruction {
+    /// How many hold intervals the interpolated output runs behind the held samples.
+    pub fn delay_intervals(self) -> usize {
+        match self {
+            Reconstruction::ZeroOrderHold | Reconstruction::Dac => 0,
+            Reconstruction::Linear => 1,
+            Reconstruction::Cubic => 2,
+        }
+    }
+    /// Get the output for a position `t` between `0.0` and `1.0` within the current hold
+    /// interval. `history` contains the last four held samples, with the most recent first.
+    pub fn interpolate(self, history: &[f32; 4], t: f32) -> f32 {
+        match self {
+            Reconstruction::ZeroOrderHold | Reconstruction::Dac => history[0],
+            Reconstruction::Linear => history[1] + ((history[0] - history[1]) * t),
+            Reconstruction::Cubic => hermite(history[3], history[2], history[1], history[0], t),
+        }
+    }
+}
+
+/// Catmull-Rom interpolation between `y1` and `y2`.
+fn hermite(y0: f32, y1: f32, y2: f32, y3: f32, t: f32) -> f32 {
+    let c1 = 0.5 * (y2 - y0);
+    let c2 = y0 - (2.5 * y1) + (2.0 * y2) - (0.5 * y3);
+    let c3 = (0.5 * (y3 - y0)) + (1.5 * (y1 - y2));
+
+    ((((c3 * t) + c2) * t + c1) * t) + y1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four held samples with the most recent first, and positions within the hold interval. All
+    /// of these and the expected values are exact in binary.
+    const HISTORY: [f32; 4] = [0.75, -0.5, 0.25, 1.0];
+    const POSITIONS: [f32; 4] = [0.0, 0.25, 0.5, 0.75];
+
+    fn curve(reconstruction: Reconstruction, history: &[f32; 4]) -> Vec<f32> {
+        POSITIONS
+            .iter()
+            .map(|&t| reconstruction.interpolate(history, t))
+            .collect()
+    }
+
+    #[test]
+    fn zero_order_hold_and_dac_hold_the_latest_sample() {
+        assert_eq!(curve(Reconstruction::ZeroOrderHold, &HISTORY), [0.75; 4]);
+        assert_eq!(curve(Reconstruction::Dac, &HISTORY), [0.75; 4]);
+    }
+
+    #[test]
+    fn linear_goes_from_the_previous_to_the_latest_sample() {
+        assert_eq!(
+            curve(Reconstruction::Linear, &HISTORY),
+            [-0.5, -0.1875, 0.125, 0.4375]
+        );
+        assert_eq!(Reconstruction::Linear.interpolate(&HISTORY, 1.0), 0.75);
+    }
+
+    #[test]
+    fn cubic_passes_through_the_held_samples() {
+        assert_eq!(Reconstruction::Cubic.interpolate(&HISTORY, 0.0), 0.25);
+        assert_eq!(Reconstruction::Cubic.interpolate(&HISTORY, 1.0), -0.5);
+    }
+
+    #[test]
+    fn cubic_overshoots_like_catmull_rom() {
+        // A single spike, halfway down its falling edge the spline is above the straight line
+        assert_eq!(
+            Reconstruction::Cubic.interpolate(&[0.0, 0.0, 1.0, 0.0], 0.5),
+            0.5625
+        );
+    }
+
+    #[test]
+    fn interpolation_reproduces_a_ramp() {
+        // Held samples from a ramp that went up by one every hold interval
+        let ramp = [3.0, 2.0, 1.0, 0.0];
+        assert_eq!(curve(Reconstruction::Linear, &ramp), [2.0, 2.25, 2.5, 2.75]);
+        assert_eq!(curve(Reconstruction::Cubic, &ramp), [1.0, 1.25, 1.5, 1.75]);
+    }
+
+    #[test]
+    fn delay_matches_the_interpolated_ramp() {
+        // On a ramp the interpolated value shows exactly how many hold intervals the output runs
+        // behind the most recent held sample
+        let ramp = [3.0, 2.0, 1.0, 0.0];
+        for reconstruction in [
+            Reconstruction::ZeroOrderHold,
+            Reconstruction::Linear,
+            Reconstruction::Cubic,
+            Reconstruction::Dac,
+        ] {
+            let delay = reconstruction.delay_intervals() as f32;
+            assert_eq!(
+                reconstruction.interpolate(&ramp, 0.0),
+                ramp[0] - delay,
+                "{reconstruction:?}"
+            );
+        }
+    }
+}
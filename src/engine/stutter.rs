@@ -0,0 +1,203 @@
+use nih_plug::prelude::*;
+use rand::Rng;
+
+/// The longest slice the stutter stage can repeat, in milliseconds. This determines how much
+/// memory gets allocated for the ring buffer.
+pub const MAX_SLICE_LENGTH_MS: f32 = 2000.0;
+
+/// The length of the crossfades between the input and a stuttered slice at either end of the
+/// slice, in samples. Without these every repeat would start and end with a click.
+const DECLICK_LENGTH: f32 = 64.0;
+
+/// How the stutter slice length is specified.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceLengthMode {
+    /// A fixed length in milliseconds.
+    #[id = "free"]
+    #[name = "Free"]
+    Free,
+    /// A note division at the host's tempo.
+    #[id = "sync"]
+    #[name = "Sync"]
+    Sync,
+}
+
+/// What happens to a slice when it gets stuttered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StutterAction {
+    Repeat,
+    Reverse,
+    OctaveUp,
+    OctaveDown,
+}
+
+/// Records the input into a ring buffer and, at the start of every slice, has a chance to replace
+/// the next slice with the previous one. The repeated slice is played as is, reversed, or pitched
+/// up or down an octave, picked at random. Slices are shared between all channels.
+pub struct Stutter {
+    /// The ring buffer for every channel. This stops recording while a slice is being repeated, so
+    /// consecutive repeats keep playing the same audio.
+    buffer: Vec<Vec<f32>>,
+    /// The next position in `buffer` to write to.
+    write_pos: usize,
+
+    /// The length of the current slice in samples. This is latched at the start of a slice.
+    length: usize,
+    /// The position within the current slice, in samples.
+    position: usize,
+    /// Where in `buffer` the repeated slice starts.
+    start: usize,
+    /// How the current slice is stuttered, or `None` if the input passes through.
+    action: Option<StutterAction>,
+}
+
+impl Stutter {
+    pub fn new(num_channels: usize, max_slice_length: usize) -> Self {
+        Self {
+            buffer: vec![vec![0.0; max_slice_length.max(1)]; num_channels],
+            write_pos: 0,
+
+            length: 0,
+            position: 0,
+            start: 0,
+            action: None,
+        }
+    }
+
+    /// Resize the ring buffers. This allocates.
+    pub fn resize(&mut self, num_channels: usize, max_slice_length: usize) {
+        *self = Self::new(num_channels, max_slice_length);
+    }
+
+    pub fn reset(&mut self) {
+        for buffer in &mut self.buffer {
+            buffer.fill(0.0);
+        }
+        self.write_pos = 0;
+        self.length = 0;
+        self.position = 0;
+        self.start = 0;
+        self.action = None;
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.first().map_or(1, Vec::len)
+    }
+
+    /// Called once at the start of every sample, before [`process()`][Self::process()] gets
+    /// called for the individual channels. Starts a new slice when the last one has ended.
+    /// `slice_length` is the slice length in samples and `probability` is the chance of that new
+    /// slice getting stuttered.
+    pub fn start_sample(&mut self, slice_length: usize, probability: f32, rng: &mut impl Rng) {
+        if self.position < self.length {
+            return;
+        }
+
+        let stutter = probability > 0.0 && rng.gen::<f32>() < probability;
+        self.position = 0;
+        if !stutter {
+            self.length = slice_length.clamp(1, self.capacity());
+            self.action = None;
+            return;
+        }
+
+        // A new stutter grabs the slice that was just recorded. Stuttering again right after keeps
+        // the same slice and length, only the action changes.
+        if self.action.is_none() {
+            self.length = slice_length.clamp(1, self.capacity());
+            self.start = (self.write_pos + self.capacity() - self.length) % self.capacity();
+        }
+        self.action = Some(match rng.gen_range(0..4) {
+            0 => StutterAction::Repeat,
+            1 => StutterAction::Reverse,
+            2 => StutterAction::OctaveUp,
+            _ => StutterAction::OctaveDown,
+        });
+    }
+
+    /// Process a single sample for `channel`. Stuttered slices fade in from and back out to
+    /// `sample`.
+    pub fn process(&mut self, channel: usize, sample: f32) -> f32 {
+        let action = match self.action {
+            Some(action) => action,
+            None => {
+                self.buffer[channel][self.write_pos] = sample;
+                return sample;
+            }
+        };
+
+        let read_pos = match action {
+            StutterAction::Repeat => self.position as f32,
+            StutterAction::Reverse => (self.length - 1 - self.position) as f32,
+            StutterAction::OctaveUp => ((self.position * 2) % self.length) as f32,
+            StutterAction::OctaveDown => self.position as f32 * 0.5,
+        };
+        let buffer = &self.buffer[channel];
+        let index = read_pos as usize;
+        let t = read_pos.fract();
+        let current = buffer[(self.start + index) % buffer.len()];
+        let next = buffer[(self.start + index + 1) % buffer.len()];
+        let stuttered = current + ((next - current) * t);
+
+        let remaining = (self.length - self.position) as f32;
+        let crossfade = (((self.position + 1) as f32).min(remaining) / DECLICK_LENGTH).min(1.0);
+
+        sample + ((stuttered - sample) * crossfade)
+    }
+
+    /// Called once at the end of every sample, after all channels have been processed.
+    pub fn end_sample(&mut self) {
+        if self.action.is_none() {
+            self.write_pos = (self.write_pos + 1) % self.capacity();
+        }
+        self.position += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    const SLICE_LENGTH: usize = 256;
+
+    /// Record the first slice of `input` and stutter every slice after that, returns the output.
+    fn stutter(input: impl Iterator<Item = f32>) -> Vec<f32> {
+        let mut stutter = Stutter::new(1, SLICE_LENGTH);
+        let mut rng = StdRng::seed_from_u64(19);
+
+        input
+            .enumerate()
+            .map(|(i, sample)| {
+                let probability = if i < SLICE_LENGTH { 0.0 } else { 1.0 };
+                stutter.start_sample(SLICE_LENGTH, probability, &mut rng);
+                let output = stutter.process(0, sample);
+                stutter.end_sample();
+                output
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crossfades_are_seamless_for_constant_input() {
+        // Fading to silence at the slice edges would dip here
+        let output = stutter(std::iter::repeat_n(0.5, SLICE_LENGTH * 8));
+        assert!(output.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn slice_edges_start_and_end_at_the_input() {
+        let input: Vec<f32> = (0..SLICE_LENGTH * 8)
+            .map(|i| (i as f32 * 0.05).sin())
+            .collect();
+        let output = stutter(input.iter().copied());
+
+        for start in (SLICE_LENGTH..input.len()).step_by(SLICE_LENGTH) {
+            let end = start + SLICE_LENGTH - 1;
+            // The crossfades stay within one `DECLICK_LENGTH`th of the input at the edges
+            let tolerance = 2.0 / DECLICK_LENGTH;
+            assert!((output[start] - input[start]).abs() <= tolerance, "{start}");
+            assert!((output[end] - input[end]).abs() <= tolerance, "{end}");
+        }
+    }
+}
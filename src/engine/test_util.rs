@@ -0,0 +1,25 @@
+//! Helpers shared between the engine's tests.
+
+use std::f64::consts::TAU;
+
+/// The energy of `signal` between the normalized frequencies `low` and `high`, both as a fraction
+/// of the sample rate between `0.0` and `0.5`. This is a plain DFT over the whole signal, so keep
+/// the signals short.
+pub fn band_energy(signal: &[f32], low: f64, high: f64) -> f64 {
+    let length = signal.len();
+    let first_bin = (low * length as f64).ceil() as usize;
+    let last_bin = ((high * length as f64).floor() as usize).min(length / 2);
+
+    (first_bin..=last_bin)
+        .map(|bin| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, &sample) in signal.iter().enumerate() {
+                let angle = TAU * (bin * i) as f64 / length as f64;
+                re += sample as f64 * angle.cos();
+                im -= sample as f64 * angle.sin();
+            }
+
+            (re * re) + (im * im)
+        })
+        .sum()
+}
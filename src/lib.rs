@@ -1,37 +1,229 @@
 use nih_plug::prelude::*;
+use nih_plug::wrapper::state::{ParamValue, PluginState};
 use nih_plug_vizia::ViziaState;
-use rand::prelude::*;
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+use engine::{
+    AntiAliasing, BitErrors, BitMangler, BitShiftMode, ClipMode, ClipPlacement, Concealment,
+    CrushMode, CrusherEngine, DitherMode, EntropyDepths, EntropyRateMode, EntropySourceKind,
+    MixLaw, NoiseShaping, NoteDivision, Oversampling, QuantizerMode, Reconstruction, ReduxMode,
+    ReduxPhase, SliceLengthMode, MAX_PACKET_LENGTH_MS, MAX_REDUX, MAX_SLICE_LENGTH_MS,
+    MAX_WORD_BITS, MIN_BIT_DEPTH, MIN_REDUX_HZ,
+};
+
 mod editor;
+pub mod engine;
 
 pub struct EntropeRust {
     params: Arc<EntropeRustParams>,
-    gen: rand::rngs::StdRng,
+    engine: CrusherEngine,
+
+    /// Set in `reset()` so the engine gets reset at the start of the next block, after the block's
+    /// parameter values have been pushed to it.
+    needs_reset: bool,
+    /// Set in `reset()` so the RNG gets reseeded at the start of the next block when running in
+    /// deterministic mode.
+    needs_reseed: bool,
+    /// Where we expect the transport to be at the start of the next block, used to detect jumps in
+    /// deterministic mode.
+    expected_pos_samples: Option<i64>,
+    /// Whether the transport was playing during the last block. Starting playback counts as a jump
+    /// in deterministic mode, since the RNG keeps running while the transport is stopped.
+    was_playing: bool,
+    /// The latency last reported to the host, so it's only reported again when it changes.
+    latency_samples: u32,
 }
 
 #[derive(Params)]
 struct EntropeRustParams {
     #[id = "bit_rate"]
-    pub bit_depth: IntParam,
+    pub bit_depth: FloatParam,
+
+    #[id = "crush_mode"]
+    pub crush_mode: EnumParam<CrushMode>,
+
+    #[id = "mu"]
+    pub mu: FloatParam,
+
+    #[id = "a"]
+    pub a: FloatParam,
+
+    #[id = "mantissa_bits"]
+    pub mantissa_bits: IntParam,
+
+    #[id = "exponent_bits"]
+    pub exponent_bits: IntParam,
+
+    #[id = "quantizer_mode"]
+    pub quantizer_mode: EnumParam<QuantizerMode>,
+
+    #[id = "dither_mode"]
+    pub dither_mode: EnumParam<DitherMode>,
+
+    #[id = "dither_amount"]
+    pub dither_amount: FloatParam,
+
+    #[id = "noise_shaping"]
+    pub noise_shaping: EnumParam<NoiseShaping>,
+
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<Oversampling>,
+
+    /// The bit mangler's masks, MSB first.
+    #[nested(array, group = "Bit")]
+    pub bits: [BitParams; MAX_WORD_BITS as usize],
+
+    #[id = "bit_shift_mode"]
+    pub bit_shift_mode: EnumParam<BitShiftMode>,
+
+    #[id = "bit_shift"]
+    pub bit_shift: IntParam,
+
+    /// The bit error rate at full entropy.
+    #[id = "bit_error_rate"]
+    pub bit_error_rate: FloatParam,
+
+    #[id = "bit_error_weighting"]
+    pub bit_error_weighting: FloatParam,
+
+    #[id = "redux_mode"]
+    pub redux_mode: EnumParam<ReduxMode>,
 
     #[id = "sample_rate"]
     pub sample_rate: IntParam,
 
+    #[id = "redux_hz"]
+    pub redux_hz: FloatParam,
+
+    #[id = "redux_phase"]
+    pub redux_phase: EnumParam<ReduxPhase>,
+
+    #[id = "redux_jitter"]
+    pub redux_jitter: FloatParam,
+
+    #[id = "redux_jitter_correlation"]
+    pub redux_jitter_correlation: FloatParam,
+
+    #[id = "anti_aliasing"]
+    pub anti_aliasing: EnumParam<AntiAliasing>,
+
+    #[id = "reconstruction"]
+    pub reconstruction: EnumParam<Reconstruction>,
+
     #[id = "entropy"]
-    pub entropy: IntParam,
+    pub entropy: FloatParam,
+
+    #[id = "entropy_floor"]
+    pub entropy_floor: FloatParam,
+
+    /// Reseed the RNG from `seed` and the transport position on resets and transport jumps, so
+    /// renders are repeatable.
+    #[id = "deterministic"]
+    pub deterministic: BoolParam,
+
+    #[id = "entropy_source"]
+    pub entropy_source: EnumParam<EntropySourceKind>,
+
+    #[id = "entropy_rate_mode"]
+    pub entropy_rate_mode: EnumParam<EntropyRateMode>,
+
+    #[id = "entropy_rate"]
+    pub entropy_rate: FloatParam,
+
+    #[id = "entropy_sync"]
+    pub entropy_sync: EnumParam<NoteDivision>,
+
+    #[id = "entropy_slew"]
+    pub entropy_slew: FloatParam,
+
+    #[id = "entropy_to_crush"]
+    pub entropy_to_crush: FloatParam,
+
+    #[id = "entropy_to_redux"]
+    pub entropy_to_redux: FloatParam,
+
+    #[id = "entropy_to_gain"]
+    pub entropy_to_gain: FloatParam,
+
+    #[id = "entropy_to_mix"]
+    pub entropy_to_mix: FloatParam,
+
+    #[id = "entropy_to_dropout"]
+    pub entropy_to_dropout: FloatParam,
+
+    #[id = "entropy_to_stutter"]
+    pub entropy_to_stutter: FloatParam,
+
+    #[id = "stutter_length_mode"]
+    pub stutter_length_mode: EnumParam<SliceLengthMode>,
+
+    #[id = "stutter_length"]
+    pub stutter_length: FloatParam,
+
+    #[id = "stutter_sync"]
+    pub stutter_sync: EnumParam<NoteDivision>,
+
+    #[id = "packet_loss"]
+    pub packet_loss: FloatParam,
+
+    #[id = "packet_length"]
+    pub packet_length: FloatParam,
+
+    #[id = "concealment"]
+    pub concealment: EnumParam<Concealment>,
+
+    #[id = "gain"]
+    pub gain: FloatParam,
+
+    #[id = "mix"]
+    pub mix: FloatParam,
+
+    #[id = "mix_law"]
+    pub mix_law: EnumParam<MixLaw>,
+
+    #[id = "clip_mode"]
+    pub clip_mode: EnumParam<ClipMode>,
+
+    #[id = "clip"]
+    pub clip: FloatParam,
+
+    #[id = "clip_placement"]
+    pub clip_placement: EnumParam<ClipPlacement>,
+
+    /// The seed used in deterministic mode.
+    #[persist = "seed"]
+    pub seed: AtomicU64,
 
-    // #[id = "clip"]
-    // pub clip: FloatParam,
     #[persist = "editor-state"]
     editor_state: Arc<ViziaState>,
 }
 
+/// The toggles for a single bit in the bit mangler.
+#[derive(Params)]
+struct BitParams {
+    /// The bit gets cleared when this is disabled.
+    #[id = "mask"]
+    pub mask: BoolParam,
+
+    /// The bit gets flipped when this is enabled.
+    #[id = "xor"]
+    pub xor: BoolParam,
+}
+
 impl Default for EntropeRust {
     fn default() -> Self {
         Self {
             params: Arc::new(EntropeRustParams::default()),
-            gen: StdRng::from_entropy(),
+            // This gets resized to match the actual layout in `initialize()`
+            engine: CrusherEngine::new(2),
+
+            needs_reset: true,
+            needs_reseed: true,
+            expected_pos_samples: None,
+            was_playing: false,
+            latency_samples: 0,
         }
     }
 }
@@ -40,14 +232,287 @@ impl Default for EntropeRustParams {
     fn default() -> Self {
         Self {
             editor_state: editor::default_state(),
+            seed: AtomicU64::new(rand::thread_rng().gen()),
+            bit_depth: FloatParam::new(
+                "bit rate",
+                24.0,
+                // This used to be an integer parameter. Keeping the same range keeps the
+                // normalized values, and thus existing automation, the same.
+                FloatRange::Linear {
+                    min: 2.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" bits")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            crush_mode: EnumParam::new("Crush Mode", CrushMode::Linear),
+            mu: FloatParam::new(
+                "Mu",
+                255.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 255.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            a: FloatParam::new(
+                "A",
+                87.6,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 87.6,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            // These default to fp16
+            mantissa_bits: IntParam::new("Mantissa", 10, IntRange::Linear { min: 0, max: 23 })
+                .with_unit(" bits"),
+            exponent_bits: IntParam::new("Exponent", 5, IntRange::Linear { min: 2, max: 8 })
+                .with_unit(" bits"),
+            quantizer_mode: EnumParam::new("Rounding", QuantizerMode::Truncate),
+            dither_mode: EnumParam::new("Dither", DitherMode::Off),
+            dither_amount: FloatParam::new(
+                "Dither Amount",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            noise_shaping: EnumParam::new("Noise Shaping", NoiseShaping::Off),
+            oversampling: EnumParam::new("Oversampling", Oversampling::Off),
+            bits: std::array::from_fn(BitParams::new),
+            bit_shift_mode: EnumParam::new("Bit Shift Mode", BitShiftMode::Rotate),
+            bit_shift: IntParam::new(
+                "Bit Shift",
+                0,
+                IntRange::Linear {
+                    min: -(MAX_WORD_BITS as i32 - 1),
+                    max: MAX_WORD_BITS as i32 - 1,
+                },
+            ),
+            bit_error_rate: FloatParam::new(
+                "Bit Errors",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 0.1,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(3))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            // Negative values put the errors in the LSBs, positive values in the MSBs
+            bit_error_weighting: FloatParam::new(
+                "Bit Error Weighting",
+                -0.5,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            redux_mode: EnumParam::new("Redux Mode", ReduxMode::Divide),
+            sample_rate: IntParam::new(
+                "sample rate",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_REDUX,
+                },
+            ),
+            // Anything at or above the host's sample rate is passed through as is
+            redux_hz: FloatParam::new(
+                "Redux Rate",
+                192_000.0,
+                FloatRange::Skewed {
+                    min: MIN_REDUX_HZ,
+                    max: 192_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            redux_phase: EnumParam::new("Redux Phase", ReduxPhase::Linked),
+            redux_jitter: FloatParam::new(
+                "Redux Jitter",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" oct")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            redux_jitter_correlation: FloatParam::new(
+                "Jitter Correlation",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            anti_aliasing: EnumParam::new("Anti-Aliasing", AntiAliasing::Off),
+            reconstruction: EnumParam::new("Reconstruction", Reconstruction::ZeroOrderHold),
+            // This used to be an integer percentage. The normalized values are still the same so
+            // automation carries over, saved state gets converted in `filter_state()`.
+            entropy: FloatParam::new("Entropy", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            entropy_floor: FloatParam::new(
+                "Entropy Floor",
+                MIN_BIT_DEPTH,
+                FloatRange::Linear {
+                    min: MIN_BIT_DEPTH,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" bits")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            deterministic: BoolParam::new("Deterministic", false),
+            entropy_source: EnumParam::new("Entropy Source", EntropySourceKind::Uniform),
+            entropy_rate_mode: EnumParam::new("Entropy Rate Mode", EntropyRateMode::Hz),
+            // At the top of the range this picks a new value for every sample
+            entropy_rate: FloatParam::new(
+                "Entropy Rate",
+                10.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-2.5),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            entropy_sync: EnumParam::new("Entropy Sync", NoteDivision::Sixteenth),
+            entropy_slew: FloatParam::new(
+                "Entropy Slew",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            // This used to be the only thing Entropy did, so it's the only target that's enabled by
+            // default
+            entropy_to_crush: entropy_depth_param("Entropy > Crush", 1.0),
+            entropy_to_redux: entropy_depth_param("Entropy > Redux", 0.0),
+            entropy_to_gain: entropy_depth_param("Entropy > Gain", 0.0),
+            entropy_to_mix: entropy_depth_param("Entropy > Mix", 0.0),
+            entropy_to_dropout: entropy_depth_param("Entropy > Dropout", 0.0),
+            entropy_to_stutter: entropy_depth_param("Entropy > Stutter", 0.0),
+            stutter_length_mode: EnumParam::new("Stutter Length Mode", SliceLengthMode::Free),
+            stutter_length: FloatParam::new(
+                "Stutter Length",
+                125.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: MAX_SLICE_LENGTH_MS,
+                    factor: FloatRange::skew_factor(-1.5),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            stutter_sync: EnumParam::new("Stutter Sync", NoteDivision::Sixteenth),
+            packet_loss: FloatParam::new(
+                "Packet Loss",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            packet_length: FloatParam::new(
+                "Packet Length",
+                20.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: MAX_PACKET_LENGTH_MS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            concealment: EnumParam::new("Concealment", Concealment::Silence),
             // This gain is stored as linear gain. NIH-plug comes with useful conversion functions
             // to treat these kinds of parameters as if we were dealing with decibels. Storing this
             // as decibels is easier to work with, but requires a conversion for every sample.
-            bit_depth: IntParam::new("bit rate", 24, IntRange::Linear { min: 2, max: 24 }),
-            sample_rate: IntParam::new("sample rate", 1, IntRange::Linear { min: 1, max: 100 }),
-            entropy: IntParam::new("Entropy", 0, IntRange::Linear { min: 0, max: 100 }),
-            // clip: FloatParam::new("Clip", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            gain: FloatParam::new(
+                "Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-24.0),
+                    max: util::db_to_gain(24.0),
+                    factor: FloatRange::gain_skew_factor(-24.0, 24.0),
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            mix_law: EnumParam::new("Mix Law", MixLaw::Linear),
+            clip_mode: EnumParam::new("Clip Mode", ClipMode::Off),
+            // Just like the gain this is stored as a linear gain
+            clip: FloatParam::new(
+                "Clip",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-36.0),
+                    max: util::db_to_gain(0.0),
+                    factor: FloatRange::gain_skew_factor(-36.0, 0.0),
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            clip_placement: EnumParam::new("Clip Placement", ClipPlacement::PostCrush),
+        }
+    }
+}
+
+/// A 0-100% depth for one of Entropy's modulation targets.
+fn entropy_depth_param(name: &str, default: f32) -> FloatParam {
+    FloatParam::new(name, default, FloatRange::Linear { min: 0.0, max: 1.0 })
+        .with_unit("%")
+        .with_value_to_string(formatters::v2s_f32_percentage(0))
+        .with_string_to_value(formatters::s2v_f32_percentage())
+}
+
+impl BitParams {
+    fn new(bit: usize) -> Self {
+        Self {
+            mask: BoolParam::new(format!("Bit {} Mask", bit + 1), true),
+            xor: BoolParam::new(format!("Bit {} XOR", bit + 1), false),
+        }
+    }
+}
+
+impl EntropeRustParams {
+    fn bit_mangler(&self) -> BitMangler {
+        let mut bit_mangler = BitMangler {
+            shift: self.bit_shift.value(),
+            shift_mode: self.bit_shift_mode.value(),
+            ..BitMangler::default()
+        };
+        for (i, bit) in self.bits.iter().enumerate() {
+            let flag = 1 << (MAX_WORD_BITS as usize - 1 - i);
+            if !bit.mask.value() {
+                bit_mangler.and_mask &= !flag;
+            }
+            if bit.xor.value() {
+                bit_mangler.xor_mask |= flag;
+            }
         }
+
+        bit_mangler
     }
 }
 
@@ -88,6 +553,24 @@ impl Plugin for EntropeRust {
     // tasks.
     type BackgroundTask = ();
 
+    fn filter_state(state: &mut PluginState) {
+        // The bit depth and Entropy used to be integer parameters, which are stored as an `I32`.
+        // These don't load into a `FloatParam`.
+        if let Some(ParamValue::I32(bit_depth)) = state.params.get("bit_rate") {
+            let bit_depth = *bit_depth as f32;
+            state
+                .params
+                .insert(String::from("bit_rate"), ParamValue::F32(bit_depth));
+        }
+        // Entropy was a 0-100 percentage
+        if let Some(ParamValue::I32(entropy)) = state.params.get("entropy") {
+            let entropy = *entropy as f32 / 100.0;
+            state
+                .params
+                .insert(String::from("entropy"), ParamValue::F32(entropy));
+        }
+    }
+
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
@@ -98,16 +581,27 @@ impl Plugin for EntropeRust {
 
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
+        let num_channels = audio_io_layout
+            .main_output_channels
+            .map(NonZeroU32::get)
+            .unwrap_or_default();
+        self.engine.set_num_channels(num_channels as usize);
+        self.engine.set_sample_rate(buffer_config.sample_rate);
+        self.engine
+            .set_oversampling(self.params.oversampling.value());
+        self.latency_samples = self.engine.latency_samples();
+        context.set_latency_samples(self.latency_samples);
+
         true
     }
 
     fn reset(&mut self) {
-        // Reset buffers and envelopes here. This can be called from the audio thread and may not
-        // allocate. You can remove this function if you do not need it.
+        self.needs_reset = true;
+        self.needs_reseed = true;
     }
 
     fn process(
@@ -116,65 +610,122 @@ impl Plugin for EntropeRust {
         _aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        let mut bit_depth = self.params.bit_depth.value();
-        let sample_rate = self.params.sample_rate.value();
-        let current_sample_rate = context.transport().sample_rate;
-        let entropy = self.params.entropy.value();
-        // let clip = self.params.clip.value();
-        // let mut clip_max = 0.0;
-        // let mut clip_min = 0.0;
-
-        if entropy > 0 {
-            let new = entropy + 100;
-            let n = self.gen.gen_range(0..=new);
-            bit_depth = bit_depth * (1 - (n / 100));
+        let transport = context.transport();
+        self.engine.set_bit_depth(self.params.bit_depth.value());
+        self.engine.set_crush_mode(self.params.crush_mode.value());
+        self.engine.set_mu(self.params.mu.value());
+        self.engine.set_a(self.params.a.value());
+        self.engine
+            .set_mantissa_bits(self.params.mantissa_bits.value() as u32);
+        self.engine
+            .set_exponent_bits(self.params.exponent_bits.value() as u32);
+        self.engine
+            .set_quantizer_mode(self.params.quantizer_mode.value());
+        self.engine.set_dither_mode(self.params.dither_mode.value());
+        self.engine
+            .set_dither_amount(self.params.dither_amount.value());
+        self.engine
+            .set_noise_shaping(self.params.noise_shaping.value());
+        self.engine
+            .set_oversampling(self.params.oversampling.value());
+        if self.engine.latency_samples() != self.latency_samples {
+            self.latency_samples = self.engine.latency_samples();
+            context.set_latency_samples(self.latency_samples);
         }
-
-        // if clip < 1.0 {
-        // let mut max: f32 = 0.0;
-        // let mut min: f32 = 0.0;
-        // for sample in buffer.as_slice_immutable().concat() {
-        // if sample < max {
-        // max = sample
-        // }
-        // if sample > min {
-        // min = sample
-        // }
-        // }
-
-        // clip_max = clip * max;
-        // clip_min = clip * min;
-        // }
-
-        // TODO still kinda seems like this is happening per channel
-        let mut reduced: f32 = 0.0;
-
-        let base: u32 = 2;
-        let total_q_levels = base.pow(bit_depth as u32);
-
-        for (i, channel_samples) in buffer.iter_samples().enumerate() {
-            for sample in channel_samples.into_iter() {
-                let remainder = *sample % (1.0 / total_q_levels as f32);
-
-                *sample -= remainder;
-
-                if sample_rate > 1 {
-                    let modulo = i as i32 % sample_rate;
-                    if modulo != 0 {
-                        *sample = reduced;
-                    } else {
-                        reduced = *sample;
-                    }
-                }
-
-                // if clip_max != 0.0 && *sample < clip_max {
-                // *sample = clip_max
-                // }
-                // if clip_min != 0.0 && *sample > clip_min {
-                // *sample = clip_min
-                // }
+        self.engine.set_bit_mangler(self.params.bit_mangler());
+        self.engine.set_bit_errors(BitErrors {
+            rate: self.params.bit_error_rate.value(),
+            weighting: self.params.bit_error_weighting.value(),
+        });
+        self.engine.set_redux_mode(self.params.redux_mode.value());
+        self.engine.set_redux(self.params.sample_rate.value());
+        self.engine.set_redux_hz(self.params.redux_hz.value());
+        self.engine.set_redux_phase(self.params.redux_phase.value());
+        self.engine
+            .set_redux_jitter(self.params.redux_jitter.value());
+        self.engine
+            .set_redux_jitter_correlation(self.params.redux_jitter_correlation.value());
+        self.engine
+            .set_anti_aliasing(self.params.anti_aliasing.value());
+        self.engine
+            .set_reconstruction(self.params.reconstruction.value());
+        self.engine.set_entropy(self.params.entropy.value());
+        self.engine
+            .set_entropy_floor(self.params.entropy_floor.value());
+        let entropy_rate = match self.params.entropy_rate_mode.value() {
+            EntropyRateMode::Hz => self.params.entropy_rate.value(),
+            EntropyRateMode::Sync => {
+                let tempo = transport.tempo.unwrap_or(120.0);
+                self.params.entropy_sync.value().rate_hz(tempo)
+            }
+        };
+        self.engine.set_entropy_rate(entropy_rate);
+        self.engine
+            .set_entropy_source(self.params.entropy_source.value());
+        self.engine.set_entropy_depths(EntropyDepths {
+            bit_depth: self.params.entropy_to_crush.value(),
+            redux: self.params.entropy_to_redux.value(),
+            gain: self.params.entropy_to_gain.value(),
+            mix: self.params.entropy_to_mix.value(),
+            dropout: self.params.entropy_to_dropout.value(),
+            stutter: self.params.entropy_to_stutter.value(),
+        });
+        // Synced slices at very slow tempos can't be longer than the ring buffer
+        let stutter_length = match self.params.stutter_length_mode.value() {
+            SliceLengthMode::Free => self.params.stutter_length.value(),
+            SliceLengthMode::Sync => {
+                let tempo = transport.tempo.unwrap_or(120.0);
+                self.params.stutter_sync.value().length_ms(tempo)
             }
+        };
+        self.engine
+            .set_stutter_length_ms(stutter_length.min(MAX_SLICE_LENGTH_MS));
+        self.engine.set_packet_loss(self.params.packet_loss.value());
+        self.engine
+            .set_packet_length_ms(self.params.packet_length.value());
+        self.engine.set_concealment(self.params.concealment.value());
+        self.engine.set_gain(self.params.gain.value());
+        self.engine.set_mix(self.params.mix.value());
+        self.engine.set_mix_law(self.params.mix_law.value());
+        self.engine
+            .set_entropy_slew(self.params.entropy_slew.value());
+        self.engine.set_clip_mode(self.params.clip_mode.value());
+        self.engine.set_clip_threshold(self.params.clip.value());
+        self.engine
+            .set_clip_placement(self.params.clip_placement.value());
+
+        let pos_samples = transport.pos_samples();
+        let deterministic = self.params.deterministic.value();
+        let started = transport.playing && !self.was_playing;
+        let jumped = deterministic && (started || pos_samples != self.expected_pos_samples);
+        // The parameters have already been pushed to the engine at this point, so the smoothers
+        // snap to this block's values instead of whatever was played before the reset or jump
+        if self.needs_reset || jumped {
+            self.engine.reset();
+            self.needs_reset = false;
         }
+        if deterministic && (self.needs_reseed || jumped) {
+            let seed = self.params.seed.load(Ordering::Relaxed);
+            let offset = pos_samples.unwrap_or(0) as u64;
+            self.engine.set_seed(seed.wrapping_add(offset));
+            self.needs_reseed = false;
+        }
+        self.expected_pos_samples = match pos_samples {
+            Some(pos_samples) if transport.playing => Some(pos_samples + buffer.samples() as i64),
+            pos_samples => pos_samples,
+        };
+        self.was_playing = transport.playing;
+
+        // Follow the host's beat grid so synced draws land on the beat, and only free run when the
+        // transport is stopped. This comes after the reset since that restarts the clock.
+        if self.params.entropy_rate_mode.value() == EntropyRateMode::Sync {
+            if let Some(pos_beats) = transport.pos_beats().filter(|_| transport.playing) {
+                let division = self.params.entropy_sync.value();
+                self.engine.sync_entropy_clock(pos_beats / division.beats());
+            }
+        }
+
+        self.engine.process_block(buffer.as_slice());
 
         ProcessStatus::Normal
     }